@@ -0,0 +1,132 @@
+use std::collections::{BTreeSet, HashMap};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use map_generator::context::GenerationContext;
+use map_generator::continent_generator::generate_continents;
+use map_generator::settings::Settings;
+use map_generator::terrain_generator::generate_map;
+use map_generator::utils::generate_colors;
+use map_generator::world::{Position, World};
+
+
+const SIZE: (u32, u32) = (4096, 4096);
+const SMALL_SIZE: (u32, u32) = (512, 512);
+
+// The fill of the baseline: HashMaps of visited pixels and of pixels to visit, taking the last key of the
+// unvisited map every step. Walking the keys makes every step as slow as the map is big
+fn original_continents(world: &World) -> Vec<Vec<Position>> {
+    let mut continents = Vec::new();
+    let mut checked_points: HashMap<(u32, u32), bool> = HashMap::new();
+    for i in 0..world.size.0 {
+        for j in 0..world.size.1 {
+            if checked_points.contains_key(&(i, j)) {
+                continue;
+            }
+            if !world.terrain[Position::new(i, j)].is_land() {
+                checked_points.insert((i, j), true);
+                continue;
+            }
+            continents.push(original_continent_find(&mut checked_points, world, (i, j)));
+        }
+    }
+    continents
+}
+
+fn original_continent_find(checked_points: &mut HashMap<(u32, u32), bool>, world: &World, start: (u32, u32)) -> Vec<Position> {
+    let world_bounds = (world.size.0 - 1, world.size.1 - 1);
+    let mut members = Vec::new();
+    let mut unchecked_points: HashMap<(u32, u32), bool> = HashMap::new();
+    unchecked_points.insert(start, true);
+    while !unchecked_points.is_empty() {
+        let current = *unchecked_points.keys().last().unwrap();
+        checked_points.insert(current, true);
+        if !world.terrain[Position::new(current.0, current.1)].is_land() {
+            unchecked_points.remove(&current);
+            continue;
+        }
+        let mut around = Vec::with_capacity(4);
+        if current.0 != 0 {
+            around.push((current.0 - 1, current.1));
+        }
+        if current.1 != 0 {
+            around.push((current.0, current.1 - 1));
+        }
+        if current.0 != world_bounds.0 {
+            around.push((current.0 + 1, current.1));
+        }
+        if current.1 != world_bounds.1 {
+            around.push((current.0, current.1 + 1));
+        }
+        for next in around {
+            if !unchecked_points.contains_key(&next) && !checked_points.contains_key(&next) {
+                unchecked_points.insert(next, true);
+            }
+        }
+        unchecked_points.remove(&current);
+        members.push(Position::new(current.0, current.1));
+    }
+    members
+}
+
+// The deterministic fill of the seeding change: a HashMap of visited pixels and an ordered set of pixels to visit
+fn btree_set_continents(world: &World) -> Vec<Vec<Position>> {
+    let mut continents = Vec::new();
+    let mut checked_points: HashMap<Position, bool> = HashMap::new();
+    for position in world.positions() {
+        if checked_points.contains_key(&position) {
+            continue;
+        }
+        if !world.terrain[position].is_land() {
+            checked_points.insert(position, true);
+            continue;
+        }
+        let mut members = Vec::new();
+        let mut unchecked_points: BTreeSet<usize> = BTreeSet::new();
+        unchecked_points.insert(world.index(position));
+        while let Some(current) = unchecked_points.pop_last() {
+            let position = world.position(current);
+            checked_points.insert(position, true);
+            if !world.terrain[position].is_land() {
+                continue;
+            }
+            for neighbor in world.neighbors(position) {
+                if !checked_points.contains_key(&neighbor) {
+                    unchecked_points.insert(world.index(neighbor));
+                }
+            }
+            members.push(position);
+        }
+        continents.push(members);
+    }
+    continents
+}
+
+fn continents(c: &mut Criterion) {
+    let colors = generate_colors();
+    // The original fill takes about an hour on the big map, so it only runs on the small one
+    for (size, with_original) in [(SMALL_SIZE, true), (SIZE, false)] {
+        let settings = Settings { world_size: size, ..Settings::default() };
+        let world = generate_map(&settings);
+        let mut group = c.benchmark_group(format!("continents {}x{}", size.0, size.1));
+        group.sample_size(10);
+        group.bench_function("stack", |b| b.iter_batched(
+            || world.clone(),
+            |mut world| {
+                let mut random = ChaCha8Rng::seed_from_u64(0);
+                generate_continents(&mut world, settings.min_province_size, colors.clone(), &mut random, &mut GenerationContext::new()).unwrap()
+            },
+            BatchSize::LargeInput
+        ));
+        group.bench_function("btree_set", |b| b.iter(|| btree_set_continents(&world)));
+        if with_original {
+            group.bench_function("original", |b| b.iter(|| original_continents(&world)));
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, continents);
+criterion_main!(benches);
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use crate::province_generator::Province;
+use crate::World;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    // Land provinces of the same continent
+    LandLand,
+    // A land province and a water province, the coast
+    LandSea,
+    SeaSea,
+    // Land provinces of different continents
+    CrossContinent
+}
+
+impl EdgeKind {
+    pub const ALL: [EdgeKind; 4] = [EdgeKind::LandLand, EdgeKind::LandSea, EdgeKind::SeaSea, EdgeKind::CrossContinent];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EdgeKind::LandLand => "land_land",
+            EdgeKind::LandSea => "land_sea",
+            EdgeKind::SeaSea => "sea_sea",
+            EdgeKind::CrossContinent => "cross_continent"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        EdgeKind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+// Two provinces touching each other, `first` is always the smaller id
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub first: u32,
+    pub second: u32,
+    // Pairs of neighboring pixels across the border, with moore connectivity the diagonal pairs count too
+    pub border: u32,
+    pub kind: EdgeKind
+}
+
+// Edges between all provinces of the world, built from the final province of every pixel
+#[derive(Clone, Default)]
+pub struct AdjacencyGraph {
+    // Sorted by first and second
+    edges: Vec<Edge>,
+    // Sorted edge indices of every province
+    province_edges: Vec<Vec<u32>>
+}
+
+impl AdjacencyGraph {
+    // Every pair of neighboring pixels is counted once, from the pixel with the smaller index
+    pub fn build(world: &World) -> Self {
+        let mut borders: BTreeMap<(u32, u32), u32> = BTreeMap::new();
+        for position in world.positions() {
+            let Some(province_id) = world.province_id[position].get() else {
+                continue;
+            };
+            let index = world.index(position);
+            for neighbor in world.neighbors(position) {
+                if world.index(neighbor) <= index {
+                    continue;
+                }
+                match world.province_id[neighbor].get() {
+                    Some(neighbor_id) if neighbor_id != province_id => {
+                        *borders.entry((province_id.min(neighbor_id), province_id.max(neighbor_id))).or_insert(0) += 1;
+                    },
+                    _ => {}
+                }
+            }
+        }
+        let mut province_edges: Vec<Vec<u32>> = vec![Vec::new(); world.provinces.len()];
+        let edges: Vec<Edge> = borders.into_iter().enumerate().map(|(index, ((first, second), border))| {
+            province_edges[first as usize].push(index as u32);
+            province_edges[second as usize].push(index as u32);
+            let kind = edge_kind(&world.provinces[first as usize], &world.provinces[second as usize]);
+            Edge { first, second, border, kind }
+        }).collect();
+        AdjacencyGraph { edges, province_edges }
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    pub fn edges_of(&self, province_id: u32) -> impl Iterator<Item = &Edge> {
+        self.province_edges.get(province_id as usize).into_iter().flatten().map(|index| &self.edges[*index as usize])
+    }
+
+    // Sorted by id
+    pub fn neighbors(&self, province_id: u32) -> Vec<u32> {
+        let mut neighbors: Vec<u32> = self.edges_of(province_id).map(|edge| {
+            if edge.first == province_id { edge.second } else { edge.first }
+        }).collect();
+        neighbors.sort_unstable();
+        neighbors
+    }
+
+    pub fn edge(&self, first: u32, second: u32) -> Option<&Edge> {
+        let key = (first.min(second), first.max(second));
+        self.edges.binary_search_by_key(&key, |edge| (edge.first, edge.second)).ok().map(|index| &self.edges[index])
+    }
+
+    // Replaces the neighbors the provinces got while they were generated
+    pub fn update_neighbors(&self, provinces: &mut [Province]) {
+        for province in provinces.iter_mut() {
+            province.neighbors = self.neighbors(province.id);
+        }
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut lines = String::from("first,second,border,kind\n");
+        for edge in self.edges.iter() {
+            let _ = writeln!(lines, "{},{},{},{}", edge.first, edge.second, edge.border, edge.kind.name());
+        }
+        lines
+    }
+
+    // One edge object per line inside the `edges` array
+    pub fn to_json(&self) -> String {
+        if self.edges.is_empty() {
+            return String::from("{\"edges\":[]}\n");
+        }
+        let edges: Vec<String> = self.edges.iter().map(|edge| format!(
+            "{{\"first\":{},\"second\":{},\"border\":{},\"kind\":\"{}\"}}", edge.first, edge.second, edge.border, edge.kind.name()
+        )).collect();
+        format!("{{\"edges\":[\n{}\n]}}\n", edges.join(",\n"))
+    }
+}
+
+fn edge_kind(first: &Province, second: &Province) -> EdgeKind {
+    match (first.is_land, second.is_land) {
+        (true, true) if first.continent_id != second.continent_id => EdgeKind::CrossContinent,
+        (true, true) => EdgeKind::LandLand,
+        (false, false) => EdgeKind::SeaSea,
+        _ => EdgeKind::LandSea
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{Connectivity, Grid, MaybeId, Position, Terrain, Topology};
+
+    // Provinces by pixel, 0 and 1 on continent 0, 2 on continent 1, 3 and 4 water:
+    // 0 0 1 3 4
+    // 2 2 1 3 4
+    fn small_world(connectivity: Connectivity) -> World {
+        let mut world = World::new(0, (5, 2), Topology::Flat, connectivity, Grid::Square);
+        let rows = [[0, 0, 1, 3, 4], [2, 2, 1, 3, 4]];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, id) in row.iter().enumerate() {
+                let position = Position::new(x as u32, y as u32);
+                world.province_id[position] = MaybeId::some(*id);
+                if *id >= 3 {
+                    world.terrain[position] = Terrain::Water;
+                }
+            }
+        }
+        world.provinces = (0..5).map(|id| {
+            let mut province = Province::new(id, (0, 0, 0), id < 3);
+            province.continent_id = match id {
+                0 | 1 => Some(0),
+                2 => Some(1),
+                _ => None
+            };
+            province
+        }).collect();
+        world
+    }
+
+    #[test]
+    fn counts_borders_and_kinds() {
+        let graph = AdjacencyGraph::build(&small_world(Connectivity::VonNeumann));
+        let edges: Vec<(u32, u32, u32, EdgeKind)> = graph.edges().iter().map(|edge| (edge.first, edge.second, edge.border, edge.kind)).collect();
+        assert_eq!(edges, vec![
+            (0, 1, 1, EdgeKind::LandLand),
+            (0, 2, 2, EdgeKind::CrossContinent),
+            (1, 2, 1, EdgeKind::CrossContinent),
+            (1, 3, 2, EdgeKind::LandSea),
+            (3, 4, 2, EdgeKind::SeaSea)
+        ]);
+    }
+
+    #[test]
+    fn moore_counts_diagonal_pairs() {
+        let graph = AdjacencyGraph::build(&small_world(Connectivity::Moore));
+        let borders: Vec<(u32, u32, u32)> = graph.edges().iter().map(|edge| (edge.first, edge.second, edge.border)).collect();
+        assert_eq!(borders, vec![(0, 1, 2), (0, 2, 4), (1, 2, 2), (1, 3, 4), (3, 4, 4)]);
+    }
+
+    #[test]
+    fn edges_look_the_same_from_both_sides() {
+        let graph = AdjacencyGraph::build(&small_world(Connectivity::VonNeumann));
+        for first in 0..5 {
+            for second in 0..5 {
+                assert_eq!(graph.edge(first, second), graph.edge(second, first));
+                assert_eq!(graph.neighbors(first).contains(&second), graph.neighbors(second).contains(&first));
+            }
+        }
+        assert_eq!(graph.neighbors(1), vec![0, 2, 3]);
+        assert_eq!(graph.edges_of(3).count(), 2);
+    }
+
+    #[test]
+    fn exports_csv_and_json() {
+        let graph = AdjacencyGraph::build(&small_world(Connectivity::VonNeumann));
+        assert_eq!(graph.to_csv(), "first,second,border,kind\n\
+            0,1,1,land_land\n\
+            0,2,2,cross_continent\n\
+            1,2,1,cross_continent\n\
+            1,3,2,land_sea\n\
+            3,4,2,sea_sea\n");
+        assert_eq!(graph.to_json(), "{\"edges\":[\n\
+            {\"first\":0,\"second\":1,\"border\":1,\"kind\":\"land_land\"},\n\
+            {\"first\":0,\"second\":2,\"border\":2,\"kind\":\"cross_continent\"},\n\
+            {\"first\":1,\"second\":2,\"border\":1,\"kind\":\"cross_continent\"},\n\
+            {\"first\":1,\"second\":3,\"border\":2,\"kind\":\"land_sea\"},\n\
+            {\"first\":3,\"second\":4,\"border\":2,\"kind\":\"sea_sea\"}\n\
+            ]}\n");
+        assert_eq!(AdjacencyGraph::default().to_json(), "{\"edges\":[]}\n");
+    }
+}
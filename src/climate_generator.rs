@@ -0,0 +1,63 @@
+use rand::Rng;
+
+use crate::terrain_generator::{generate_noise, sea_level};
+use crate::{Settings, Terrain, World};
+
+
+// How much colder the highest peak is than the coast at the same latitude
+pub const ELEVATION_COOLING: f32 = 0.25;
+
+// Fills temperature, moisture and biome of every pixel.
+// Temperature falls from the equator in the middle rows to the poles in the first and last rows,
+// falls with the height above the sea and is shifted by the temperature noise.
+// Moisture is the moisture noise scaled to 0..1
+pub fn generate_climate(world: &mut World, settings: &Settings, random: &mut impl Rng) {
+    let temperature_seed: u32 = random.gen();
+    let moisture_seed: u32 = random.gen();
+    let temperature_noise = generate_noise(temperature_seed, world.size, &[settings.temperature_noise], world.topology, world.grid);
+    let moisture_noise = generate_noise(moisture_seed, world.size, &[settings.moisture_noise], world.topology, world.grid);
+    let temperature_shift = settings.temperature_noise.weight.abs();
+    let lowest = moisture_noise.iter().copied().fold(f64::INFINITY, f64::min);
+    let highest = moisture_noise.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let moisture_range = if highest > lowest { highest - lowest } else { 1.0 };
+    let sea_level = sea_level(world);
+    let land_range = if sea_level < 1.0 { 1.0 - sea_level } else { 1.0 };
+    let height = world.height();
+    for (index, position) in world.positions().enumerate() {
+        let latitude = (((position.y as f32 + 0.5) / height as f32) * 2.0 - 1.0).abs();
+        let terrain = world.terrain[position];
+        let land_height = if terrain.is_land() { (world.elevation[position] - sea_level) / land_range } else { 0.0 };
+        let temperature = 1.0 - latitude - ELEVATION_COOLING * land_height + (temperature_shift * temperature_noise[index]) as f32;
+        let temperature = temperature.clamp(0.0, 1.0);
+        let moisture = ((moisture_noise[index] - lowest) / moisture_range) as f32;
+        world.temperature[position] = temperature;
+        world.moisture[position] = moisture;
+        world.biome[position] = classify_biome(terrain, temperature, moisture);
+    }
+}
+
+// Whittaker diagram over temperature and moisture. Water stays water,
+// hills and mountains keep their relief unless they are cold enough to freeze
+pub fn classify_biome(terrain: Terrain, temperature: f32, moisture: f32) -> Terrain {
+    match terrain {
+        Terrain::Water => Terrain::Water,
+        _ if temperature < 0.08 => Terrain::Glacier,
+        Terrain::Hills if temperature < 0.2 => Terrain::Tundra,
+        Terrain::Hills | Terrain::Mountains => terrain,
+        _ if temperature < 0.2 => Terrain::Tundra,
+        _ if temperature < 0.45 => if moisture < 0.35 { Terrain::Steppe } else { Terrain::Taiga },
+        _ if temperature < 0.7 => match moisture {
+            moisture if moisture < 0.2 => Terrain::Desert,
+            moisture if moisture < 0.4 => Terrain::Steppe,
+            moisture if moisture < 0.6 => Terrain::Plains,
+            moisture if moisture < 0.85 => Terrain::Forest,
+            _ => Terrain::Swamp
+        },
+        _ => match moisture {
+            moisture if moisture < 0.3 => Terrain::Desert,
+            moisture if moisture < 0.5 => Terrain::Steppe,
+            moisture if moisture < 0.85 => Terrain::Jungle,
+            _ => Terrain::Swamp
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::offset::Local;
+
+use crate::error::MapGenError;
+use crate::logger::{FileLogger, Level, LogFormat, Logger};
+use crate::province_generator::ProvinceGenerator;
+use crate::sink::{GenerationStage, Sink};
+use crate::world::World;
+
+
+static RUN_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Unique inside the process thanks to the counter, and between processes thanks to the pid
+pub fn new_run_id() -> String {
+    format!(
+        "{}_{}-{}",
+        Local::now().format("%Y-%m-%d_%H-%M-%S"),
+        std::process::id(),
+        RUN_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+
+// Everything that belongs to one generation: its id, where its files go,
+// its logger and sinks. Each world gets its own context, so worlds can be
+// generated one after another or on several threads at once
+pub struct GenerationContext {
+    run_id: String,
+    run_dir: Option<PathBuf>,
+    logger: Option<Box<dyn Logger + Send>>,
+    sinks: Vec<Box<dyn Sink + Send>>,
+    // Replaces the province_shape of the settings
+    province_generator: Option<Arc<dyn ProvinceGenerator>>
+}
+
+impl GenerationContext {
+    // Nothing is logged or written
+    pub fn new() -> Self {
+        GenerationContext { run_id: new_run_id(), run_dir: None, logger: None, sinks: Vec::new(), province_generator: None }
+    }
+
+    // Creates `<root>/<run id>/` and logs into its `log.txt`. The `maps/` dir is left to the image sink,
+    // so it only exists when images are written there
+    pub fn in_dir(root: impl AsRef<Path>, level: Level, format: LogFormat) -> Result<Self, MapGenError> {
+        let mut context = GenerationContext::new();
+        let run_dir = root.as_ref().join(&context.run_id);
+        std::fs::create_dir_all(&run_dir).map_err(|err| MapGenError::io(&run_dir, err))?;
+        context.logger = Some(Box::new(FileLogger::create(run_dir.join("log.txt"), level, format)?));
+        context.run_dir = Some(run_dir);
+        Ok(context)
+    }
+
+    pub fn with_logger(mut self, logger: impl Logger + Send + 'static) -> Self {
+        self.logger = Some(Box::new(logger));
+        self
+    }
+
+    pub fn with_sink(mut self, sink: impl Sink + Send + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    pub fn with_province_generator(mut self, generator: impl ProvinceGenerator + 'static) -> Self {
+        self.province_generator = Some(Arc::new(generator));
+        self
+    }
+
+    pub fn province_generator(&self) -> Option<Arc<dyn ProvinceGenerator>> {
+        self.province_generator.clone()
+    }
+
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    pub fn run_dir(&self) -> Option<&Path> {
+        self.run_dir.as_deref()
+    }
+
+    pub fn maps_dir(&self) -> Option<PathBuf> {
+        self.run_dir.as_ref().map(|dir| dir.join("maps"))
+    }
+
+    pub fn settings_file(&self) -> Option<PathBuf> {
+        self.run_dir.as_ref().map(|dir| dir.join("settings.txt"))
+    }
+
+    pub fn enabled(&self, level: Level) -> bool {
+        self.logger.as_ref().is_some_and(|logger| logger.enabled(level))
+    }
+
+    pub fn log(&mut self, level: Level, message: &str) -> Result<(), MapGenError> {
+        match self.logger.as_mut() {
+            Some(logger) if logger.enabled(level) => logger.log(level, message),
+            _ => Ok(())
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<(), MapGenError> {
+        match self.logger.as_mut() {
+            Some(logger) => logger.flush(),
+            None => Ok(())
+        }
+    }
+
+    pub fn stage(&mut self, stage: GenerationStage, world: &World) -> Result<(), MapGenError> {
+        for sink in self.sinks.iter_mut() {
+            sink.stage(stage, world)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for GenerationContext {
+    fn default() -> Self {
+        GenerationContext::new()
+    }
+}
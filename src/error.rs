@@ -0,0 +1,53 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::settings::SettingsError;
+
+
+#[derive(Debug)]
+pub enum MapGenError {
+    Io { path: PathBuf, source: io::Error },
+    Image { path: PathBuf, source: image::ImageError },
+    Settings(SettingsError),
+    // The color palette is smaller than the number of continents
+    OutOfColors { needed_for: &'static str },
+    DuplicateResource { province_id: u32, resource: String },
+    MissingResource { province_id: u32, resource: String }
+}
+
+impl MapGenError {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        MapGenError::Io { path: path.into(), source }
+    }
+}
+
+impl fmt::Display for MapGenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapGenError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            MapGenError::Image { path, source } => write!(f, "can't save image {}: {source}", path.display()),
+            MapGenError::Settings(err) => err.fmt(f),
+            MapGenError::OutOfColors { needed_for } => write!(f, "ran out of colors for {needed_for}"),
+            MapGenError::DuplicateResource { province_id, resource } => write!(f, "resource {resource:?} already exists in province {province_id}"),
+            MapGenError::MissingResource { province_id, resource } => write!(f, "resource {resource:?} is missing in province {province_id}")
+        }
+    }
+}
+
+impl std::error::Error for MapGenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MapGenError::Io { source, .. } => Some(source),
+            MapGenError::Image { source, .. } => Some(source),
+            MapGenError::Settings(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<SettingsError> for MapGenError {
+    fn from(err: SettingsError) -> Self {
+        MapGenError::Settings(err)
+    }
+}
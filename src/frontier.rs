@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::world::Position;
+
+
+// Pixels around a growing area with a weight each. A Fenwick tree over the slots keeps the prefix
+// sums of the weights, so setting a weight, removing a pixel and a weighted pick are all O(log n).
+// Slots of removed pixels are reused, the last freed first, so picks are the same on every run
+pub struct WeightedFrontier {
+    slots: Vec<Position>,
+    weights: Vec<u32>,
+    // 1-based, tree[i] is the sum of the weights of the slots (i - lowbit(i), i]
+    tree: Vec<u64>,
+    slot_of: HashMap<Position, usize>,
+    free: Vec<usize>,
+    total: u64
+}
+
+impl WeightedFrontier {
+    pub fn new() -> Self {
+        WeightedFrontier { slots: Vec::new(), weights: Vec::new(), tree: vec![0], slot_of: HashMap::new(), free: Vec::new(), total: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slot_of.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slot_of.is_empty()
+    }
+
+    // Inserts the pixel or changes its weight
+    pub fn set(&mut self, position: Position, weight: u32) {
+        let slot = match self.slot_of.get(&position) {
+            Some(slot) => *slot,
+            None => {
+                let slot = self.free.pop().unwrap_or_else(|| self.push_slot());
+                self.slots[slot] = position;
+                self.slot_of.insert(position, slot);
+                slot
+            }
+        };
+        let old = self.weights[slot];
+        self.weights[slot] = weight;
+        self.add(slot, weight as i64 - old as i64);
+    }
+
+    pub fn remove(&mut self, position: Position) {
+        if let Some(slot) = self.slot_of.remove(&position) {
+            let old = self.weights[slot];
+            self.weights[slot] = 0;
+            self.add(slot, -(old as i64));
+            self.free.push(slot);
+        }
+    }
+
+    // Every pixel is picked with the probability of its share of the total weight
+    pub fn pick(&self, random: &mut impl Rng) -> Option<Position> {
+        if self.total == 0 {
+            return None;
+        }
+        let mut rest = random.gen_range(0..self.total);
+        let mut index = 0;
+        let mut step = (self.weights.len() + 1).next_power_of_two() / 2;
+        while step > 0 {
+            if index + step <= self.weights.len() && self.tree[index + step] <= rest {
+                index += step;
+                rest -= self.tree[index];
+            }
+            step /= 2;
+        }
+        Some(self.slots[index])
+    }
+
+    // New slots start with zero weight, so the tree value is the sum of the slots it covers before it
+    fn push_slot(&mut self) -> usize {
+        let slot = self.weights.len();
+        let index = slot + 1;
+        let lowest = index - (index & index.wrapping_neg());
+        self.slots.push(Position { x: 0, y: 0 });
+        self.weights.push(0);
+        self.tree.push(self.prefix(slot) - self.prefix(lowest));
+        slot
+    }
+
+    // Sum of the weights of the first `count` slots
+    fn prefix(&self, count: usize) -> u64 {
+        let mut sum = 0;
+        let mut index = count;
+        while index > 0 {
+            sum += self.tree[index];
+            index &= index - 1;
+        }
+        sum
+    }
+
+    fn add(&mut self, slot: usize, delta: i64) {
+        self.total = self.total.wrapping_add_signed(delta);
+        let mut index = slot + 1;
+        while index < self.tree.len() {
+            self.tree[index] = self.tree[index].wrapping_add_signed(delta);
+            index += index & index.wrapping_neg();
+        }
+    }
+}
+
+impl Default for WeightedFrontier {
+    fn default() -> Self {
+        WeightedFrontier::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    fn position(x: u32) -> Position {
+        Position::new(x, 0)
+    }
+
+    #[test]
+    fn picks_follow_weights() {
+        let weights = [1, 2, 7, 0, 10];
+        let mut frontier = WeightedFrontier::new();
+        for (x, weight) in weights.iter().enumerate() {
+            frontier.set(position(x as u32), *weight);
+        }
+        let mut random = ChaCha8Rng::seed_from_u64(1);
+        let picks = 200_000;
+        let mut counts = [0u32; 5];
+        for _ in 0..picks {
+            counts[frontier.pick(&mut random).unwrap().x as usize] += 1;
+        }
+        let total: u32 = weights.iter().sum();
+        for (count, weight) in counts.iter().zip(weights) {
+            let expected = weight as f64 / total as f64;
+            let share = *count as f64 / picks as f64;
+            assert!((share - expected).abs() < 0.005, "share {share} for weight {weight}, expected {expected}");
+        }
+    }
+
+    #[test]
+    fn set_and_remove_keep_totals() {
+        let mut frontier = WeightedFrontier::new();
+        for x in 0..10 {
+            frontier.set(position(x), x + 1);
+        }
+        assert_eq!(frontier.len(), 10);
+        assert_eq!(frontier.total, 55);
+        frontier.set(position(3), 20);
+        assert_eq!(frontier.len(), 10);
+        assert_eq!(frontier.total, 71);
+        frontier.remove(position(0));
+        frontier.remove(position(9));
+        frontier.remove(position(9));
+        assert_eq!(frontier.len(), 8);
+        assert_eq!(frontier.total, 60);
+        // Every prefix sum matches the weights, so the tree is still right after the changes
+        for count in 0..=frontier.weights.len() {
+            assert_eq!(frontier.prefix(count), frontier.weights[..count].iter().map(|weight| *weight as u64).sum::<u64>());
+        }
+        for x in 1..9 {
+            frontier.remove(position(x));
+        }
+        assert!(frontier.is_empty());
+        assert_eq!(frontier.total, 0);
+        assert_eq!(frontier.pick(&mut ChaCha8Rng::seed_from_u64(1)), None);
+    }
+
+    #[test]
+    fn never_picks_weight_zero_or_freed_slots() {
+        let mut frontier = WeightedFrontier::new();
+        frontier.set(position(0), 0);
+        frontier.set(position(1), 4);
+        frontier.set(position(2), 9);
+        frontier.set(position(3), 5);
+        frontier.remove(position(2));
+        frontier.set(position(3), 0);
+        // Takes the slot freed by the removed pixel
+        frontier.set(position(4), 3);
+        assert_eq!(frontier.weights.len(), 4);
+        let mut random = ChaCha8Rng::seed_from_u64(2);
+        for _ in 0..10_000 {
+            let picked = frontier.pick(&mut random).unwrap();
+            assert!(picked == position(1) || picked == position(4), "picked {picked:?}");
+        }
+    }
+}
@@ -1,121 +1,116 @@
-use std::time::Instant;
-use noise::{self, utils::{NoiseMap, NoiseMapBuilder, PlaneMapBuilder}, Billow, Perlin};
-use chrono::offset::Local;
-use lazy_static::lazy_static;
-
-pub mod continent_generator;
-pub mod province_generator;
-pub mod world;
-pub mod settings;
-pub mod utils;
-
-use continent_generator::generate_continents;
-use province_generator::{Province, generate_provinces};
-use world::{World, WorldPixel, Terrain};
-use settings::Settings;
-use utils::*;
-
-
-lazy_static! {
-    pub static ref START_TIME: String = Local::now().format("%Y-%m-%d_%H-%M").to_string();
-}
-
-
-macro_rules! time {
-    ($x:expr) => {{
-        let start = Instant::now();
-        let result = $x;
-        log(format!("{:?}", start.elapsed()).as_str());
-        result
-    }};
-}
-
-
-pub fn generate_world(settings: Settings) -> World {
-    necessary::check_necessary_files();
-    let colors = generate_colors();
-    let mut world = time!(generate_map(&settings));
-    world_to_image(&world);
-    let continents = time!(generate_continents(&mut world, settings.min_province_size.clone(), colors.clone()));
-    world.continents = continents;
-    continent_to_image(&world);
-    let provinces = time!(generate_provinces(&mut world, &settings, colors.clone()));
-    world.provinces = provinces;
-    province_to_image(&world);
-    return world
-}
-
-fn generate_noise(seed: u32, size: (u32, u32)) -> NoiseMap {
-    let noises = Billow::<Perlin>::new(seed);
-    PlaneMapBuilder::<_, 2>::new(&noises)
-        .set_size(size.0 as usize, size.1 as usize)
-        .build()
-}
-
-// For future
-// TODO: Use this in water province generation
-fn _delete_single_water_pixel(world: &mut World) {
-    let mut water_pixels: Vec<(u32, u32)> = Vec::new();
-    for row in world.pixels.iter() {
-        for pixel in row.iter() {
-            if !pixel.terrain.is_land() {
-                water_pixels.push(pixel.position);
-            }
-        }
-    }
-    for pixel in water_pixels {
-        if world.get_pixels_around(pixel.1 as usize, pixel.0 as usize).iter().filter(|pixel| !pixel.terrain.is_land()).count() == 0 {
-            world.get_mut_pixel(pixel.1 as usize, pixel.0 as usize).terrain = Terrain::Plains;
-        }
-    }
-}
-
-fn generate_map(settings: &Settings) -> World {
-    let terrain_map = generate_noise(settings.seed, settings.world_size);
-    let mut terrain_map = terrain_map.iter();
-    let mut pixels: Vec<Vec<WorldPixel>> = Vec::new();
-    for i in 0..settings.world_size.0 {
-        let mut pixel_row: Vec<WorldPixel> = Vec::new();
-        for j in 0..settings.world_size.1 {
-            let terrain;
-            let biome_value = *terrain_map.next().expect("");
-            if biome_value >= 0.8 {
-                terrain = Terrain::Mountains;
-            } else if biome_value >= 0.7 {
-                terrain = Terrain::Hills
-            } else if biome_value <= -0.75 {
-                terrain = Terrain::Water;
-            } else {
-                terrain = Terrain::Plains;
-            }
-            let pixel = WorldPixel {
-                position: (j, i),
-                province_id: None,
-                continent_id: None,
-                terrain: terrain
-            };
-            pixel_row.push(pixel)
-        }
-        pixels.push(pixel_row);
-    }
-    World {
-        seed: settings.seed,
-        size: settings.world_size,
-        pixels: pixels,
-        continents: Vec::new(),
-        provinces: Vec::new()
-    }
-}
-
-
-pub fn run() {
-    let settings = Settings::new(
-        (1024, 1024),
-        124122,
-        100,
-        25,
-        400,
-        100
-    );
-    let _world = time!(generate_world(settings));
-}
\ No newline at end of file
+use std::time::Instant;
+
+pub mod error;
+pub mod terrain_generator;
+pub mod climate_generator;
+pub mod river_generator;
+pub mod continent_generator;
+pub mod water_body_generator;
+pub mod province_generator;
+pub mod province_shapes;
+pub mod adjacency;
+pub mod metrics;
+pub mod frontier;
+pub mod world;
+pub mod settings;
+pub mod random;
+pub mod logger;
+pub mod sink;
+pub mod context;
+pub mod utils;
+
+use terrain_generator::{delete_single_water_pixel, generate_map, mark_coastal_pixels};
+use climate_generator::generate_climate;
+use river_generator::generate_rivers;
+use continent_generator::generate_continents;
+use water_body_generator::generate_water_bodies;
+use province_generator::{Province, find_coasts, generate_provinces, generate_water_provinces};
+use adjacency::AdjacencyGraph;
+use metrics::{measure_continents, measure_provinces};
+use world::{World, Terrain};
+use error::MapGenError;
+use settings::Settings;
+use random::{Stage, WorldRng};
+use logger::{Level, LogFormat};
+use sink::{GenerationStage, ImageSink};
+use context::GenerationContext;
+use utils::*;
+
+
+macro_rules! time {
+    ($context:expr, $name:expr, $x:expr) => {{
+        let start = Instant::now();
+        let result = $x;
+        $context.log(Level::Info, format!("{} - {:?}", $name, start.elapsed()).as_str())?;
+        result
+    }};
+}
+
+
+// Builds the world in memory, without logging or writing anything
+pub fn generate_world(settings: &Settings) -> Result<World, MapGenError> {
+    generate_world_in(settings, &mut GenerationContext::new())
+}
+
+pub fn generate_world_in(settings: &Settings, context: &mut GenerationContext) -> Result<World, MapGenError> {
+    generate_world_until(settings, GenerationStage::Provinces, context)
+}
+
+// Stops after `last_stage`, so a terrain preview doesn't wait for provinces
+pub fn generate_world_until(settings: &Settings, last_stage: GenerationStage, context: &mut GenerationContext) -> Result<World, MapGenError> {
+    settings.check()?;
+    let colors = generate_colors();
+    let random = WorldRng::new(settings.seed);
+    let mut world = time!(context, "Terrain", generate_map(settings));
+    delete_single_water_pixel(&mut world);
+    mark_coastal_pixels(&mut world);
+    world.water_bodies = time!(context, "Water bodies", generate_water_bodies(&mut world, settings));
+    time!(context, "Climate", generate_climate(&mut world, settings, &mut random.stage(Stage::Climate)));
+    time!(context, "Rivers", generate_rivers(&mut world, settings));
+    context.stage(GenerationStage::Terrain, &world)?;
+    if last_stage == GenerationStage::Terrain {
+        context.flush()?;
+        return Ok(world)
+    }
+    let continents = time!(context, "Continents", generate_continents(&mut world, settings.min_province_size, colors, &mut random.stage(Stage::Continents), context)?);
+    world.continents = continents;
+    time!(context, "Continent metrics", measure_continents(&mut world));
+    context.stage(GenerationStage::Continents, &world)?;
+    if last_stage == GenerationStage::Continents {
+        context.flush()?;
+        return Ok(world)
+    }
+    let generator = context.province_generator().unwrap_or_else(|| settings.province_shape.generator().into());
+    let mut provinces = time!(context, "Land provinces", generate_provinces(&mut world, settings, &random, generator.as_ref()));
+    time!(context, "Water provinces", generate_water_provinces(&mut world, settings, &mut provinces, &mut random.stage(Stage::WaterProvinces)));
+    find_coasts(&world, &mut provinces);
+    world.provinces = provinces;
+    world.adjacency = time!(context, "Adjacency", AdjacencyGraph::build(&world));
+    world.adjacency.update_neighbors(&mut world.provinces);
+    time!(context, "Province metrics", measure_provinces(&mut world));
+    context.stage(GenerationStage::Provinces, &world)?;
+    context.flush()?;
+    Ok(world)
+}
+
+// Creates a context with its own run directory under `root` and the settings file if it's missing
+pub fn prepare_run_dir(root: impl AsRef<std::path::Path>, level: Level, format: LogFormat) -> Result<GenerationContext, MapGenError> {
+    let created = necessary::check_necessary_files()?;
+    let mut context = GenerationContext::in_dir(root, level, format)?;
+    for file_name in created {
+        context.log(Level::Info, format!("{:?} - created", file_name).as_str())?;
+    }
+    Ok(context)
+}
+
+pub fn run() -> Result<(), MapGenError> {
+    let context = prepare_run_dir("logs", Level::Debug, LogFormat::Text)?;
+    let settings = Settings::from_file(necessary::SETTINGS_FILE)?;
+    if let Some(settings_file) = context.settings_file() {
+        save_settings(&settings, &settings_file)?;
+    }
+    let maps_dir = context.maps_dir().unwrap_or_default();
+    let mut context = context.with_sink(ImageSink::new(maps_dir, MapImage::DEFAULT.to_vec()));
+    let _world = time!(context, "World", generate_world_in(&settings, &mut context)?);
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use chrono::offset::Local;
+
+use crate::error::MapGenError;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error
+}
+
+impl Level {
+    pub const ALL: [Level; 5] = [Level::Trace, Level::Debug, Level::Info, Level::Warn, Level::Error];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Level::ALL.into_iter().find(|level| level.name() == name)
+    }
+}
+
+pub trait Logger {
+    fn log(&mut self, level: Level, message: &str) -> Result<(), MapGenError>;
+
+    // Checked before building a message, so disabled levels cost nothing in hot loops
+    fn enabled(&self, _level: Level) -> bool {
+        true
+    }
+
+    fn flush(&mut self) -> Result<(), MapGenError> {
+        Ok(())
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    // `2024-01-01T12:00:00.000 [info] message`
+    Text,
+    // `{"time":"2024-01-01T12:00:00.000","level":"info","message":"message"}`
+    Json
+}
+
+// Appends to one file, which is opened once and buffered for the whole run
+pub struct FileLogger {
+    path: PathBuf,
+    file: BufWriter<File>,
+    level: Level,
+    format: LogFormat
+}
+
+impl FileLogger {
+    pub fn create(path: impl Into<PathBuf>, level: Level, format: LogFormat) -> Result<Self, MapGenError> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| MapGenError::io(&path, err))?;
+        Ok(FileLogger { path, file: BufWriter::new(file), level, format })
+    }
+}
+
+impl Logger for FileLogger {
+    fn log(&mut self, level: Level, message: &str) -> Result<(), MapGenError> {
+        if !self.enabled(level) {
+            return Ok(());
+        }
+        let time = Local::now().format("%Y-%m-%dT%H:%M:%S%.3f");
+        let result = match self.format {
+            LogFormat::Text => writeln!(self.file, "{} [{}] {}", time, level.name(), message),
+            LogFormat::Json => writeln!(self.file, "{{\"time\":\"{}\",\"level\":\"{}\",\"message\":{}}}", time, level.name(), json_string(message))
+        };
+        result.map_err(|err| MapGenError::io(&self.path, err))?;
+        if level >= Level::Warn {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn enabled(&self, level: Level) -> bool {
+        level >= self.level
+    }
+
+    fn flush(&mut self) -> Result<(), MapGenError> {
+        self.file.flush().map_err(|err| MapGenError::io(&self.path, err))
+    }
+}
+
+
+// Hands every message to a closure, e.g. to route it into the application's own logger
+pub struct FnLogger<F: FnMut(Level, &str)> {
+    function: F,
+    level: Level
+}
+
+impl<F: FnMut(Level, &str)> FnLogger<F> {
+    pub fn new(level: Level, function: F) -> Self {
+        FnLogger { function, level }
+    }
+}
+
+impl<F: FnMut(Level, &str)> Logger for FnLogger<F> {
+    fn log(&mut self, level: Level, message: &str) -> Result<(), MapGenError> {
+        if self.enabled(level) {
+            (self.function)(level, message);
+        }
+        Ok(())
+    }
+
+    fn enabled(&self, level: Level) -> bool {
+        level >= self.level
+    }
+}
+
+
+fn json_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for symbol in value.chars() {
+        match symbol {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            symbol if (symbol as u32) < 0x20 => {
+                let _ = write!(result, "\\u{:04x}", symbol as u32);
+            },
+            symbol => result.push(symbol)
+        }
+    }
+    result.push('"');
+    result
+}
@@ -1,5 +1,244 @@
-use map_generator::run;
-
-fn main() {
-    run();
-}
\ No newline at end of file
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Parser, Subcommand};
+use map_generator::{generate_world, generate_world_until, prepare_run_dir};
+use map_generator::adjacency::EdgeKind;
+use map_generator::context::GenerationContext;
+use map_generator::error::MapGenError;
+use map_generator::logger::{Level, LogFormat};
+use map_generator::province_generator::ProvinceShape;
+use map_generator::settings::{parse_size, Settings, SettingsError};
+use map_generator::sink::{GenerationStage, ImageSink};
+use map_generator::utils::{necessary, save_settings, MapImage};
+use map_generator::water_body_generator::WaterBodyKind;
+use map_generator::world::{Connectivity, Grid, Terrain, Topology, World};
+
+
+#[derive(Parser)]
+#[command(version, about = "Generates worlds with continents and provinces")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate the whole world and write the chosen stage images
+    Generate {
+        #[command(flatten)]
+        world: WorldArgs,
+        /// Stage images to write: terrain, heightmap, biomes, rivers, water_bodies, continents, provinces, coordinates, adjacency, metrics
+        #[arg(long, value_delimiter = ',', value_parser = parse_image, default_value = "terrain,heightmap,biomes,rivers,water_bodies,continents,provinces")]
+        images: Vec<MapImage>
+    },
+    /// Run only the stages needed for the given images and write them
+    Render {
+        #[command(flatten)]
+        world: WorldArgs,
+        /// Stage images to write: terrain, heightmap, biomes, rivers, water_bodies, continents, provinces, coordinates, adjacency, metrics
+        #[arg(required = true, value_parser = parse_image)]
+        images: Vec<MapImage>
+    },
+    /// Generate the world in memory and print its statistics
+    Stats {
+        #[command(flatten)]
+        world: WorldArgs
+    }
+}
+
+#[derive(Args)]
+struct WorldArgs {
+    /// Settings file, the flags below override its values [default: gamedata/settings.txt]
+    #[arg(long)]
+    settings: Option<PathBuf>,
+    /// World size as WIDTHxHEIGHT
+    #[arg(long, value_parser = parse_size)]
+    size: Option<(u32, u32)>,
+    /// flat, cylinder (wraps east-west) or torus (wraps both ways)
+    #[arg(long, value_parser = parse_topology)]
+    topology: Option<Topology>,
+    /// von_neumann (4 neighbors) or moore (8 neighbors, diagonals too)
+    #[arg(long, value_parser = parse_connectivity)]
+    connectivity: Option<Connectivity>,
+    /// square or hex
+    #[arg(long, value_parser = parse_grid)]
+    grid: Option<Grid>,
+    #[arg(long)]
+    seed: Option<u32>,
+    #[arg(long)]
+    min_province_size: Option<u32>,
+    #[arg(long)]
+    max_province_size: Option<u32>,
+    /// weighted, voronoi or flood
+    #[arg(long, value_parser = parse_province_shape)]
+    province_shape: Option<ProvinceShape>,
+    #[arg(long)]
+    min_water_province_size: Option<u32>,
+    #[arg(long)]
+    max_water_province_size: Option<u32>,
+    /// Directory for the stage images [default: logs/<run id>/maps]
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Lowest level written to the log: trace, debug, info, warn, error
+    #[arg(long, value_parser = parse_level, default_value = "info")]
+    log_level: Level,
+    /// Write log lines as JSON objects instead of plain text
+    #[arg(long)]
+    log_json: bool
+}
+
+impl WorldArgs {
+    fn settings(&self) -> Result<Settings, SettingsError> {
+        // A settings file given on the command line must exist, only the default one may be missing
+        let mut settings = match self.settings.as_deref() {
+            Some(settings_file) => Settings::from_file(settings_file)?,
+            None if Path::new(necessary::SETTINGS_FILE).exists() => Settings::from_file(necessary::SETTINGS_FILE)?,
+            None => Settings::default()
+        };
+        if let Some(size) = self.size {
+            settings.world_size = size;
+        }
+        if let Some(topology) = self.topology {
+            settings.topology = topology;
+        }
+        if let Some(connectivity) = self.connectivity {
+            settings.connectivity = connectivity;
+        }
+        if let Some(grid) = self.grid {
+            settings.grid = grid;
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+        if let Some(size) = self.min_province_size {
+            settings.min_province_size = size;
+        }
+        if let Some(size) = self.max_province_size {
+            settings.max_province_size = size;
+        }
+        if let Some(shape) = self.province_shape {
+            settings.province_shape = shape;
+        }
+        if let Some(size) = self.min_water_province_size {
+            settings.min_water_province_size = size;
+        }
+        if let Some(size) = self.max_water_province_size {
+            settings.max_water_province_size = size;
+        }
+        settings.check()?;
+        Ok(settings)
+    }
+
+    // Writes the images into the output dir, and the log with the settings into logs/<run id>/.
+    // gamedata/ with the default settings file is only set up for runs that use both default paths
+    fn generate(&self, images: Vec<MapImage>, last_stage: GenerationStage) -> Result<World, MapGenError> {
+        let settings = self.settings()?;
+        let format = if self.log_json { LogFormat::Json } else { LogFormat::Text };
+        let context = if self.settings.is_none() && self.output.is_none() {
+            prepare_run_dir("logs", self.log_level, format)?
+        } else {
+            GenerationContext::in_dir("logs", self.log_level, format)?
+        };
+        if let Some(settings_file) = context.settings_file() {
+            save_settings(&settings, &settings_file)?;
+        }
+        let dir = self.output.clone().or(context.maps_dir()).unwrap_or_default();
+        let mut context = context.with_sink(ImageSink::new(dir, images));
+        generate_world_until(&settings, last_stage, &mut context)
+    }
+}
+
+fn parse_image(name: &str) -> Result<MapImage, String> {
+    let names: Vec<&str> = MapImage::ALL.iter().map(|image| image.name()).collect();
+    MapImage::from_name(name).ok_or_else(|| format!("unknown image {name:?}, expected one of {}", names.join(", ")))
+}
+
+fn parse_topology(name: &str) -> Result<Topology, String> {
+    Topology::from_name(name).ok_or_else(|| format!("unknown topology {name:?}, expected flat, cylinder or torus"))
+}
+
+fn parse_connectivity(name: &str) -> Result<Connectivity, String> {
+    Connectivity::from_name(name).ok_or_else(|| format!("unknown connectivity {name:?}, expected von_neumann or moore"))
+}
+
+fn parse_grid(name: &str) -> Result<Grid, String> {
+    Grid::from_name(name).ok_or_else(|| format!("unknown grid {name:?}, expected square or hex"))
+}
+
+fn parse_province_shape(name: &str) -> Result<ProvinceShape, String> {
+    ProvinceShape::from_name(name).ok_or_else(|| format!("unknown province shape {name:?}, expected weighted, voronoi or flood"))
+}
+
+fn parse_level(name: &str) -> Result<Level, String> {
+    Level::from_name(name).ok_or_else(|| format!("unknown log level {name:?}, expected trace, debug, info, warn or error"))
+}
+
+fn print_stats(world: &World) {
+    let land_pixels = world.terrain.iter().filter(|terrain| terrain.is_land()).count();
+    let all_pixels = world.area();
+    let land_provinces: Vec<usize> = world.provinces.iter().filter(|province| province.is_land).map(|province| province.elements.len()).collect();
+    let water_provinces: Vec<usize> = world.provinces.iter().filter(|province| !province.is_land).map(|province| province.elements.len()).collect();
+    println!("seed: {}", world.seed);
+    println!("size: {}x{}, {} {}, {}", world.size.0, world.size.1, world.topology.name(), world.grid.name(), world.connectivity.name());
+    println!("land: {} of {} pixels ({:.1}%)", land_pixels, all_pixels, land_pixels as f64 * 100.0 / all_pixels as f64);
+    let biomes: Vec<String> = Terrain::ALL.iter().filter(|biome| biome.is_land()).map(|biome| {
+        let count = world.biome.iter().filter(|pixel_biome| *pixel_biome == biome).count();
+        format!("{} {:.1}%", biome.name(), count as f64 * 100.0 / land_pixels.max(1) as f64)
+    }).collect();
+    println!("biomes of land: {}", biomes.join(", "));
+    let river_pixels = world.river_width.iter().filter(|width| **width > 0).count();
+    println!("rivers: {} pixels, widest {}", river_pixels, world.river_width.iter().copied().max().unwrap_or(0));
+    let water_bodies: Vec<String> = WaterBodyKind::ALL.iter().map(|kind| {
+        format!("{} {}", world.water_bodies.iter().filter(|body| body.kind == *kind).count(), kind.name())
+    }).collect();
+    println!("water bodies: {}", water_bodies.join(", "));
+    println!("continents: {}", world.continents.len());
+    print_province_stats("land provinces", &land_provinces);
+    print_province_stats("water provinces", &water_provinces);
+    let coastal_provinces = world.provinces.iter().filter(|province| province.is_land && province.is_coastal).count();
+    println!("coastal land provinces: {} of {}", coastal_provinces, land_provinces.len());
+    let edges: Vec<String> = EdgeKind::ALL.iter().map(|kind| {
+        format!("{} {}", world.adjacency.edges().iter().filter(|edge| edge.kind == *kind).count(), kind.name())
+    }).collect();
+    println!("province borders: {}", edges.join(", "));
+    let compactness: Vec<f64> = world.provinces.iter().filter(|province| province.is_land).map(|province| province.metrics.compactness).collect();
+    if !compactness.is_empty() {
+        let least = compactness.iter().copied().fold(f64::INFINITY, f64::min);
+        println!("land province compactness: least {:.3}, average {:.3}", least, compactness.iter().sum::<f64>() / compactness.len() as f64);
+    }
+}
+
+fn print_province_stats(name: &str, sizes: &[usize]) {
+    if sizes.is_empty() {
+        println!("{name}: 0");
+        return;
+    }
+    let min = sizes.iter().min().unwrap();
+    let max = sizes.iter().max().unwrap();
+    let average = sizes.iter().sum::<usize>() as f64 / sizes.len() as f64;
+    println!("{name}: {} (size min {min}, average {average:.1}, max {max})", sizes.len());
+}
+
+fn run(command: Command) -> Result<(), MapGenError> {
+    match command {
+        Command::Generate { world, images } => {
+            world.generate(images, GenerationStage::Provinces)?;
+        },
+        Command::Render { world, images } => {
+            let last_stage = images.iter().map(|image| image.stage()).max().unwrap_or(GenerationStage::Terrain);
+            world.generate(images, last_stage)?;
+        },
+        Command::Stats { world } => {
+            print_stats(&generate_world(&world.settings()?)?);
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(err) = run(cli.command) {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
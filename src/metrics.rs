@@ -0,0 +1,258 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::fmt::Write as _;
+
+use crate::world::{Grid, Layer, MaybeId, Position};
+use crate::World;
+
+
+// Cells covered by a region. On a wrapping map the box can go across the seam,
+// then x + width or y + height is past the size of the map
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BoundingBox {
+    // Top left cell
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RegionMetrics {
+    // Cells
+    pub area: u32,
+    // Mean (x, y) of the cells, can be outside of a bent region
+    pub centroid: (f64, f64),
+    // The cell farthest from the border of the region, the pole of inaccessibility.
+    // Of equally far cells the one nearest to the centroid
+    pub anchor: Position,
+    pub bounding_box: BoundingBox,
+    // Cell sides on the border, towards other regions, cells without a region and the map edge
+    pub perimeter: u32,
+    // Polsby-Popper score 4π·area / perimeter², 1 for a circle and near 0 for snakes.
+    // The cell sides make the border longer, so round regions stay near π/4 on squares
+    pub compactness: f64
+}
+
+// Metrics of the regions 0..count of the id layer, like the provinces or the continents.
+// Every region is walked from its first cell, steps are summed up instead of wrapped,
+// so a region across a seam gets one centroid and box instead of two halves
+pub fn measure_regions(world: &World, ids: &Layer<MaybeId>, count: usize) -> Vec<RegionMetrics> {
+    let mut metrics = vec![RegionMetrics::default(); count];
+    let mut sums = vec![(0i64, 0i64); count];
+    // Lowest and highest unwrapped x and y
+    let mut extents = vec![(i64::MAX, i64::MIN, i64::MAX, i64::MIN); count];
+    let mut visited = Layer::new(world.size, false);
+    for start in world.positions() {
+        let Some(id) = ids[start].get() else {
+            continue;
+        };
+        if visited[start] {
+            continue;
+        }
+        let region = &mut metrics[id as usize];
+        let (sum, extent) = (&mut sums[id as usize], &mut extents[id as usize]);
+        visited[start] = true;
+        let mut unchecked = vec![(start, start.x as i64, start.y as i64)];
+        while let Some((position, x, y)) = unchecked.pop() {
+            region.area += 1;
+            *sum = (sum.0 + x, sum.1 + y);
+            *extent = (extent.0.min(x), extent.1.max(x), extent.2.min(y), extent.3.max(y));
+            region.perimeter += world.side_offsets(position).iter()
+                .filter(|(dx, dy)| world.step(position, *dx, *dy).is_none_or(|side| ids[side] != ids[position]))
+                .count() as u32;
+            for (dx, dy) in world.neighbor_offsets(position) {
+                match world.step(position, *dx, *dy) {
+                    Some(next) if !visited[next] && ids[next] == ids[position] => {
+                        visited[next] = true;
+                        unchecked.push((next, x + *dx as i64, y + *dy as i64));
+                    },
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let (width, height) = (world.width() as i64, world.height() as i64);
+    let (cell_area, side_length) = match world.grid {
+        Grid::Square => (1.0, 1.0),
+        Grid::Hex => (3f64.sqrt() / 2.0, 1.0 / 3f64.sqrt())
+    };
+    for ((region, sum), extent) in metrics.iter_mut().zip(sums.iter()).zip(extents.iter()) {
+        if region.area == 0 {
+            continue;
+        }
+        let area = region.area as f64;
+        let (mut x, mut y) = (sum.0 as f64 / area, sum.1 as f64 / area);
+        if world.topology.wraps_east_west() {
+            x = x.rem_euclid(width as f64);
+        }
+        if world.topology.wraps_north_south() {
+            y = y.rem_euclid(height as f64);
+        }
+        region.centroid = (x, y);
+        let box_width = (extent.1 - extent.0 + 1).min(width);
+        let box_height = (extent.3 - extent.2 + 1).min(height);
+        region.bounding_box = BoundingBox {
+            x: if box_width == width { 0 } else { extent.0.rem_euclid(width) as u32 },
+            y: if box_height == height { 0 } else { extent.2.rem_euclid(height) as u32 },
+            width: box_width as u32,
+            height: box_height as u32
+        };
+        let perimeter = region.perimeter as f64 * side_length;
+        region.compactness = if perimeter > 0.0 { 4.0 * PI * area * cell_area / (perimeter * perimeter) } else { 1.0 };
+    }
+
+    // Steps from the border of the region, breadth first from all border cells at once
+    let mut distance = vec![u32::MAX; world.area()];
+    let mut queue: VecDeque<Position> = VecDeque::new();
+    for position in world.positions() {
+        if ids[position].is_none() {
+            continue;
+        }
+        let on_border = world.side_offsets(position).iter()
+            .any(|(dx, dy)| world.step(position, *dx, *dy).is_none_or(|side| ids[side] != ids[position]));
+        if on_border {
+            distance[world.index(position)] = 0;
+            queue.push_back(position);
+        }
+    }
+    while let Some(position) = queue.pop_front() {
+        let next_distance = distance[world.index(position)] + 1;
+        for neighbor in world.neighbors(position) {
+            if ids[neighbor] == ids[position] && distance[world.index(neighbor)] == u32::MAX {
+                distance[world.index(neighbor)] = next_distance;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    let mut best: Vec<Option<(u32, f64)>> = vec![None; count];
+    for position in world.positions() {
+        let Some(id) = ids[position].get() else {
+            continue;
+        };
+        let region = &mut metrics[id as usize];
+        let (mut dx, mut dy) = (position.x as f64 - region.centroid.0, position.y as f64 - region.centroid.1);
+        if world.topology.wraps_east_west() {
+            dx -= width as f64 * (dx / width as f64).round();
+        }
+        if world.topology.wraps_north_south() {
+            dy -= height as f64 * (dy / height as f64).round();
+        }
+        let candidate = (distance[world.index(position)], dx * dx + dy * dy);
+        let is_better = match best[id as usize] {
+            Some((steps, to_centroid)) => candidate.0 > steps || (candidate.0 == steps && candidate.1 < to_centroid),
+            None => true
+        };
+        if is_better {
+            best[id as usize] = Some(candidate);
+            region.anchor = position;
+        }
+    }
+    metrics
+}
+
+pub fn measure_provinces(world: &mut World) {
+    let province_metrics = measure_regions(world, &world.province_id, world.provinces.len());
+    for (province, metrics) in world.provinces.iter_mut().zip(province_metrics) {
+        province.metrics = metrics;
+    }
+}
+
+pub fn measure_continents(world: &mut World) {
+    let continent_metrics = measure_regions(world, &world.continent_id, world.continents.len());
+    for (continent, metrics) in world.continents.iter_mut().zip(continent_metrics) {
+        continent.metrics = metrics;
+    }
+}
+
+// One line per continent and province
+pub fn metrics_to_csv(world: &World) -> String {
+    let mut lines = String::from("region,id,area,centroid_x,centroid_y,anchor_x,anchor_y,box_x,box_y,box_width,box_height,perimeter,compactness\n");
+    let continents = world.continents.iter().map(|continent| ("continent", continent.id, &continent.metrics));
+    let provinces = world.provinces.iter().map(|province| (if province.is_land { "land_province" } else { "water_province" }, province.id, &province.metrics));
+    for (region, id, metrics) in continents.chain(provinces) {
+        let bounding_box = metrics.bounding_box;
+        let _ = writeln!(
+            lines, "{region},{id},{},{:.2},{:.2},{},{},{},{},{},{},{},{:.4}",
+            metrics.area, metrics.centroid.0, metrics.centroid.1, metrics.anchor.x, metrics.anchor.y,
+            bounding_box.x, bounding_box.y, bounding_box.width, bounding_box.height, metrics.perimeter, metrics.compactness
+        );
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{Connectivity, Topology};
+
+    // One region over the listed cells of an otherwise empty map
+    fn measure_one(world: &World, cells: &[(u32, u32)]) -> RegionMetrics {
+        let mut ids = Layer::new(world.size, MaybeId::NONE);
+        for (x, y) in cells {
+            ids[Position::new(*x, *y)] = MaybeId::some(0);
+        }
+        measure_regions(world, &ids, 1)[0]
+    }
+
+    fn block(xs: std::ops::Range<u32>, ys: std::ops::Range<u32>) -> Vec<(u32, u32)> {
+        ys.flat_map(|y| xs.clone().map(move |x| (x, y))).collect()
+    }
+
+    #[test]
+    fn region_across_the_seam_of_a_cylinder_is_one_piece() {
+        let world = World::new(0, (8, 4), Topology::Cylinder, Connectivity::VonNeumann, Grid::Square);
+        let mut cells = block(6..8, 1..3);
+        cells.extend(block(0..2, 1..3));
+        let metrics = measure_one(&world, &cells);
+        assert_eq!(metrics.area, 8);
+        assert_eq!(metrics.centroid, (7.5, 1.5));
+        assert_eq!(metrics.bounding_box, BoundingBox { x: 6, y: 1, width: 4, height: 2 });
+        assert_eq!(metrics.perimeter, 12);
+    }
+
+    #[test]
+    fn region_across_both_seams_of_a_torus_is_one_piece() {
+        let world = World::new(0, (8, 4), Topology::Torus, Connectivity::VonNeumann, Grid::Square);
+        let metrics = measure_one(&world, &[(7, 3), (0, 3), (7, 0), (0, 0)]);
+        assert_eq!(metrics.centroid, (7.5, 3.5));
+        assert_eq!(metrics.bounding_box, BoundingBox { x: 7, y: 3, width: 2, height: 2 });
+        assert_eq!(metrics.perimeter, 8);
+    }
+
+    // The centroid of an L falls outside of it, the anchor stays inside, in the corner
+    #[test]
+    fn anchor_of_an_l_is_inside_it() {
+        let world = World::new(0, (10, 10), Topology::Flat, Connectivity::VonNeumann, Grid::Square);
+        let mut cells = block(0..3, 0..10);
+        cells.extend(block(3..10, 7..10));
+        let metrics = measure_one(&world, &cells);
+        let centroid_cell = (metrics.centroid.0.round() as u32, metrics.centroid.1.round() as u32);
+        assert!(!cells.contains(&centroid_cell));
+        assert!(cells.contains(&(metrics.anchor.x, metrics.anchor.y)));
+        assert_eq!(metrics.anchor, Position::new(2, 7));
+    }
+
+    #[test]
+    fn square_on_squares() {
+        let world = World::new(0, (5, 5), Topology::Flat, Connectivity::VonNeumann, Grid::Square);
+        let metrics = measure_one(&world, &block(1..4, 1..4));
+        assert_eq!(metrics.perimeter, 12);
+        assert!((metrics.compactness - PI / 4.0).abs() < 1e-9);
+    }
+
+    // Rows next to each other share 5 sides, so the block has 9 * 6 - 2 * (3 * 2 + 2 * 5) = 22 sides on its border
+    #[test]
+    fn square_on_hexes() {
+        let world = World::new(0, (5, 5), Topology::Flat, Connectivity::VonNeumann, Grid::Hex);
+        let metrics = measure_one(&world, &block(1..4, 1..4));
+        assert_eq!(metrics.perimeter, 22);
+        let expected = 4.0 * PI * 9.0 * 3f64.sqrt() / 2.0 / (22.0 * 22.0 / 3.0);
+        assert!((metrics.compactness - expected).abs() < 1e-9);
+        // A lone hex is a regular hexagon
+        let metrics = measure_one(&world, &[(2, 2)]);
+        assert_eq!(metrics.perimeter, 6);
+        assert!((metrics.compactness - PI / (2.0 * 3f64.sqrt())).abs() < 1e-9);
+    }
+}
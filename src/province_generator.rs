@@ -1,156 +1,453 @@
-use std::collections::HashMap;
-
-use rand::{self, Rng, thread_rng};
-
-use crate::{Settings, World, WorldPixel};
-
-
-#[derive(Clone)]
-pub struct Province {
-    pub id: u32,
-    pub continent_id: Option<u32>,
-    pub elements: Vec<(u32, u32)>,
-    pub color: (u8, u8, u8),
-    pub is_land: bool,
-    pub neighbors: Vec<u32>,
-    pub resources: HashMap<String, u32>
-}
-
-impl Province {
-    pub fn new(id: u32, color: (u8, u8, u8), is_land: bool) -> Self {
-        Province { id, elements: Vec::new(), continent_id: None, color, is_land, neighbors: Vec::new(), resources: HashMap::new() }
-    }
-
-    pub fn add_pixel(&mut self, pixel: &mut WorldPixel) {
-        pixel.province_id = Some(self.id);
-        self.elements.push(pixel.position);
-    }
-
-    pub fn add_province_neighbor(&mut self, neighbor_province: &mut Province) {
-        self.neighbors.push(neighbor_province.id);
-        neighbor_province.neighbors.push(self.id);
-    }
-
-    pub fn set_resource(&mut self, resource_name: &str, value: u32) {
-        if self.resources.contains_key(resource_name) {
-            panic!("Resource {resource_name:} is already exist in {:}", self.id)
-        }
-        self.resources.insert(resource_name.to_string(), value);
-    }
-}
-
-pub fn generate_provinces(world: &mut World, settings: &Settings, colors: Vec<(u8, u8, u8)>) -> Vec<Province> {
-    let mut random = thread_rng();
-    let mut colors = colors;
-    let mut provinces: Vec<Province> = Vec::new();
-    let mut current_id = 0;
-    for continent in world.continents.clone() {
-        let mut unchecked_pixels: HashMap<(u32, u32), bool> = HashMap::new();
-        unchecked_pixels.extend(continent.members.clone().iter().map(|element| (*element, true)));
-        while !unchecked_pixels.is_empty() {
-            let color_pos = random.gen_range(0..colors.len());
-            let random_pixel = unchecked_pixels.keys().skip(random.gen_range(0..unchecked_pixels.keys().len())).next().unwrap();
-            let mut province = generate_land_province(current_id, random_pixel.clone(), settings.max_province_size, world, continent.id, colors[color_pos].clone(), settings.min_province_size);
-            for pixel in province.elements.iter() {
-                unchecked_pixels.remove(pixel);
-            }
-            if (province.elements.len() as u32) < settings.min_province_size {
-                let neighbors: HashMap<u32, u32> = found_province_neighbor(&province, world).iter().map(|id| {
-                    (*id, provinces.get(*id as usize).unwrap().elements.len() as u32)
-                }).collect();
-                let minimal = *neighbors.values().min().unwrap();
-                let possible_province: Vec<(&u32, &u32)> = neighbors.iter().filter(|(_id, length)| {
-                    **length == minimal
-                }).collect();
-                let new_province = provinces.get_mut(*(possible_province.get(random.gen_range(0..possible_province.len())).unwrap().0) as usize).unwrap();
-                for pixel in province.elements.iter() {
-                    new_province.add_pixel(world.get_mut_pixel(pixel.1 as usize, pixel.0 as usize));
-                }
-                continue;
-            }
-            colors.swap_remove(color_pos);
-            
-            let neighbors = found_province_neighbor(&province, &world);
-            for neighbor in neighbors {
-                province.add_province_neighbor(provinces.get_mut(neighbor as usize).unwrap());
-            }
-            provinces.push(province);
-            current_id += 1;
-        }
-    }
-
-    return provinces;
-}
-
-pub fn found_province_neighbor(province: &Province, world: &World) -> Vec<u32> {
-    let mut provinces: Vec<u32> = Vec::new();
-    for pixel in province.elements.iter() {
-        for neighbors in world.get_pixels_around(pixel.1 as usize, pixel.0 as usize).iter() {
-            if neighbors.province_id.is_some() && neighbors.province_id.unwrap() != province.id && !provinces.contains(&neighbors.province_id.unwrap()) {
-                provinces.push(neighbors.province_id.unwrap());
-            }
-        }
-    }
-    return provinces
-}
-
-pub fn generate_land_province(id: u32, first_pixel: (u32, u32), max_size: u32, world: &mut World, continent_id: u32, color: (u8, u8, u8), min_size: u32) -> Province {
-    let mut random = thread_rng();
-    let mut province = Province::new(id, color, true);
-    let mut province_size = 0;
-    let mut possible_pixels: HashMap<(u32, u32), u8> = HashMap::new();
-    let mut anyway_added: Vec<(u32, u32)> = Vec::new();
-    let mut added_pixels: Vec<(u32, u32)> = Vec::new();
-    let result_province_size = random.gen_range(((max_size - min_size).max(min_size))..max_size);
-    anyway_added.push(first_pixel);
-    loop {
-        if !anyway_added.is_empty() {
-            for pixel in anyway_added.iter() {
-                province.add_pixel(world.get_mut_pixel(pixel.1 as usize, pixel.0 as usize));
-                added_pixels.push(*pixel);
-                match possible_pixels.get(pixel) {
-                    Some(_) => {possible_pixels.remove(pixel);},
-                    None => {}
-                };
-            }
-            province_size += anyway_added.len() as u32;
-            anyway_added.clear();
-        } else if province_size >= result_province_size || possible_pixels.is_empty() {
-            break;
-        } else {
-            let mut variants = Vec::new();
-            for (position, count) in possible_pixels.iter() {
-                variants.extend(std::iter::repeat(position).take((count.clone() + (count.clone() - 1) * 2) as usize));
-            }
-            let added_pixel: (u32, u32) = variants[random.gen_range(0..(variants.len()))];
-            province.add_pixel(world.get_mut_pixel(added_pixel.1 as usize, added_pixel.0 as usize));
-            possible_pixels.remove(&added_pixel);
-            added_pixels.push(added_pixel);
-            province_size += 1;
-        }
-        for position in added_pixels.iter() {
-            let around = world.get_pixels_around(position.1 as usize, position.0 as usize);
-            for pixel in around {
-                if pixel.terrain.is_land() && pixel.continent_id.unwrap() == continent_id && pixel.province_id.is_none() {
-                    match possible_pixels.get_mut(&pixel.position) {
-                        Some(value) => {
-                            *value += 1;
-                        },
-                        None => {possible_pixels.insert(pixel.position, 1);}
-                    }
-                } else {
-                    possible_pixels.remove(position);
-                }
-            }
-        }
-        for (possible_pixel, pixel_count) in possible_pixels.iter() {
-            let around_pixel = world.get_pixels_around(possible_pixel.1 as usize, possible_pixel.0 as usize); 
-            if around_pixel.iter().filter(|pixel| pixel.terrain.is_land() && pixel.continent_id.unwrap() == continent_id).count() as u8 == *pixel_count {
-                anyway_added.push(*possible_pixel);
-            }
-        }
-        added_pixels.clear()
-    }
-    
-    return province;
-}
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use rand::{Rng, seq::SliceRandom};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+
+use crate::continent_generator::Continent;
+use crate::frontier::WeightedFrontier;
+use crate::metrics::RegionMetrics;
+use crate::province_shapes::{halve_cell, FloodProvinces, VoronoiProvinces};
+use crate::random::{Stage, WorldRng};
+use crate::world::{MaybeId, Position};
+use crate::utils::province_color;
+use crate::{MapGenError, Settings, World};
+
+
+#[derive(Clone)]
+pub struct Province {
+    pub id: u32,
+    pub continent_id: Option<u32>,
+    pub elements: Vec<Position>,
+    pub color: (u8, u8, u8),
+    pub is_land: bool,
+    pub neighbors: Vec<u32>,
+    pub resources: HashMap<String, u32>,
+    // Land touching water, or water touching land
+    pub is_coastal: bool,
+    // Pixel sides shared with the other kind of terrain
+    pub coastline_length: u32,
+    // Only for land provinces, sorted by id
+    pub water_body_ids: Vec<u32>,
+    pub sea_province_ids: Vec<u32>,
+    // Filled once all provinces are final
+    pub metrics: RegionMetrics
+}
+
+impl Province {
+    pub fn new(id: u32, color: (u8, u8, u8), is_land: bool) -> Self {
+        Province {
+            id,
+            elements: Vec::new(),
+            continent_id: None,
+            color,
+            is_land,
+            neighbors: Vec::new(),
+            resources: HashMap::new(),
+            is_coastal: false,
+            coastline_length: 0,
+            water_body_ids: Vec::new(),
+            sea_province_ids: Vec::new(),
+            metrics: RegionMetrics::default()
+        }
+    }
+
+    pub fn add_pixel(&mut self, map: &mut impl ProvinceMap, position: Position) {
+        map.set_province_id(position, self.id);
+        self.elements.push(position);
+    }
+
+    pub fn add_province_neighbor(&mut self, neighbor_province: &mut Province) {
+        self.neighbors.push(neighbor_province.id);
+        neighbor_province.neighbors.push(self.id);
+    }
+
+    pub fn set_resource(&mut self, resource_name: &str, value: u32) -> Result<(), MapGenError> {
+        if self.resources.contains_key(resource_name) {
+            return Err(MapGenError::DuplicateResource { province_id: self.id, resource: resource_name.to_string() });
+        }
+        self.resources.insert(resource_name.to_string(), value);
+        Ok(())
+    }
+}
+
+// Where growing provinces look up and mark the province of every pixel
+pub trait ProvinceMap {
+    fn world(&self) -> &World;
+    fn province_id(&self, position: Position) -> MaybeId;
+    fn set_province_id(&mut self, position: Position, id: u32);
+}
+
+impl ProvinceMap for World {
+    fn world(&self) -> &World {
+        self
+    }
+
+    fn province_id(&self, position: Position) -> MaybeId {
+        self.province_id[position]
+    }
+
+    fn set_province_id(&mut self, position: Position, id: u32) {
+        self.province_id[position] = MaybeId::some(id);
+    }
+}
+
+const NO_PROVINCE: u32 = u32::MAX;
+
+// Province ids shared by the threads of all continents. Continents don't touch,
+// so every pixel is only ever read and written by the thread of its own continent
+pub struct ContinentProvinceMap<'a> {
+    pub world: &'a World,
+    province_ids: &'a [AtomicU32]
+}
+
+impl ContinentProvinceMap<'_> {
+    pub fn clear_province_id(&mut self, position: Position) {
+        self.province_ids[self.world.index(position)].store(NO_PROVINCE, Ordering::Relaxed);
+    }
+}
+
+impl ProvinceMap for ContinentProvinceMap<'_> {
+    fn world(&self) -> &World {
+        self.world
+    }
+
+    fn province_id(&self, position: Position) -> MaybeId {
+        match self.province_ids[self.world.index(position)].load(Ordering::Relaxed) {
+            NO_PROVINCE => MaybeId::NONE,
+            id => MaybeId::some(id)
+        }
+    }
+
+    fn set_province_id(&mut self, position: Position, id: u32) {
+        self.province_ids[self.world.index(position)].store(id, Ordering::Relaxed);
+    }
+}
+
+// Splits one continent into land provinces. Ids and neighbors count from 0 in every continent,
+// colors are given afterwards. Every member of the continent must end up in one province and every province
+// must be in one piece. Weighted merges undersized provinces into their smallest neighbor, which can go over
+// max_province_size, the other shapes halve those again
+pub trait ProvinceGenerator: Send + Sync {
+    fn continent_provinces(&self, map: &mut ContinentProvinceMap, continent: &Continent, settings: &Settings, random: &mut ChaCha8Rng) -> Vec<Province>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProvinceShape {
+    // Grows one province after another, preferring pixels touching it from several sides
+    Weighted,
+    // Voronoi cells of Poisson-disk seeds, relaxed with Lloyd's algorithm
+    Voronoi,
+    // All provinces of a continent grow at once from random seeds
+    Flood
+}
+
+impl ProvinceShape {
+    pub const ALL: [ProvinceShape; 3] = [ProvinceShape::Weighted, ProvinceShape::Voronoi, ProvinceShape::Flood];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProvinceShape::Weighted => "weighted",
+            ProvinceShape::Voronoi => "voronoi",
+            ProvinceShape::Flood => "flood"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        ProvinceShape::ALL.into_iter().find(|shape| shape.name() == name)
+    }
+
+    pub fn generator(&self) -> Box<dyn ProvinceGenerator> {
+        match self {
+            ProvinceShape::Weighted => Box::new(WeightedGrowth),
+            ProvinceShape::Voronoi => Box::new(VoronoiProvinces::default()),
+            ProvinceShape::Flood => Box::new(FloodProvinces)
+        }
+    }
+}
+
+// Province size the growth aims for on average, the same for every generator
+pub fn average_province_size(settings: &Settings) -> f64 {
+    let smallest_target = (settings.max_province_size - settings.min_province_size).max(settings.min_province_size);
+    (smallest_target + settings.max_province_size) as f64 / 2.0
+}
+
+// Land provinces of all continents grow at the same time, each continent from its own random stream.
+// Ids are given afterwards in continent order, so the result doesn't depend on the threads
+pub fn generate_provinces(world: &mut World, settings: &Settings, random: &WorldRng, generator: &dyn ProvinceGenerator) -> Vec<Province> {
+    let province_ids: Vec<AtomicU32> = (0..world.area()).map(|_| AtomicU32::new(NO_PROVINCE)).collect();
+    let shared_world: &World = world;
+    let continent_provinces: Vec<Vec<Province>> = shared_world.continents.par_iter().map(|continent| {
+        let mut map = ContinentProvinceMap { world: shared_world, province_ids: &province_ids };
+        generator.continent_provinces(&mut map, continent, settings, &mut random.item(Stage::Provinces, continent.id))
+    }).collect();
+
+    let mut provinces: Vec<Province> = Vec::new();
+    for continent_provinces in continent_provinces {
+        let first_id = provinces.len() as u32;
+        for mut province in continent_provinces {
+            province.id += first_id;
+            province.color = province_color(province.id, true);
+            for neighbor in province.neighbors.iter_mut() {
+                *neighbor += first_id;
+            }
+            for position in province.elements.iter() {
+                world.province_id[*position] = MaybeId::some(province.id);
+            }
+            provinces.push(province);
+        }
+    }
+    provinces
+}
+
+pub struct WeightedGrowth;
+
+impl ProvinceGenerator for WeightedGrowth {
+    fn continent_provinces(&self, map: &mut ContinentProvinceMap, continent: &Continent, settings: &Settings, random: &mut ChaCha8Rng) -> Vec<Province> {
+        generate_continent_provinces(map, continent, settings, random)
+    }
+}
+
+fn generate_continent_provinces(map: &mut impl ProvinceMap, continent: &Continent, settings: &Settings, random: &mut impl Rng) -> Vec<Province> {
+    let mut provinces: Vec<Province> = Vec::new();
+    // Walking the shuffled members is the same as picking a random unchecked pixel each time
+    let mut start_pixels = continent.members.clone();
+    start_pixels.shuffle(random);
+    for start_pixel in start_pixels {
+        if map.province_id(start_pixel).is_some() {
+            continue;
+        }
+        let mut province = generate_land_province(provinces.len() as u32, start_pixel, settings.max_province_size, map, continent.id, settings.min_province_size, random);
+        let neighbors: BTreeMap<u32, u32> = found_province_neighbor(&province, map).iter().map(|id| {
+            (*id, provinces[*id as usize].elements.len() as u32)
+        }).collect();
+        // Merge into the smallest neighbor, a province without one stays as it is
+        if (province.elements.len() as u32) < settings.min_province_size && !neighbors.is_empty() {
+            let minimal = *neighbors.values().min().unwrap();
+            let possible_province: Vec<(&u32, &u32)> = neighbors.iter().filter(|(_id, length)| {
+                **length == minimal
+            }).collect();
+            let new_province = &mut provinces[*possible_province[random.gen_range(0..possible_province.len())].0 as usize];
+            for position in province.elements.iter() {
+                new_province.add_pixel(map, *position);
+            }
+            continue;
+        }
+        for neighbor in neighbors.keys() {
+            province.add_province_neighbor(&mut provinces[*neighbor as usize]);
+        }
+        provinces.push(province);
+    }
+    provinces
+}
+
+pub fn found_province_neighbor(province: &Province, map: &impl ProvinceMap) -> Vec<u32> {
+    let mut provinces: Vec<u32> = Vec::new();
+    for position in province.elements.iter() {
+        for neighbor in map.world().neighbors(*position) {
+            if let Some(neighbor_id) = map.province_id(neighbor).get() {
+                if neighbor_id != province.id && !provinces.contains(&neighbor_id) {
+                    provinces.push(neighbor_id);
+                }
+            }
+        }
+    }
+    provinces
+}
+
+pub fn generate_water_provinces(world: &mut World, settings: &Settings, provinces: &mut Vec<Province>, random: &mut impl Rng) {
+    let mut current_id = provinces.len() as u32;
+    let mut start_pixels: Vec<Position> = world.positions().filter(|position| !world.terrain[*position].is_land()).collect();
+    start_pixels.shuffle(random);
+    for start_pixel in start_pixels {
+        if world.province_id[start_pixel].is_some() {
+            continue;
+        }
+        let mut province = Province::new(current_id, province_color(current_id, false), false);
+        grow_province(&mut province, start_pixel, settings.min_water_province_size, settings.max_water_province_size, world, |world, position| !world.terrain[position].is_land(), random);
+        if (province.elements.len() as u32) < settings.min_water_province_size {
+            merge_small_water_province(&mut province, world, settings, provinces, random);
+            if province.elements.is_empty() {
+                continue;
+            }
+        }
+
+        for neighbor in found_province_neighbor(&province, world) {
+            province.add_province_neighbor(provinces.get_mut(neighbor as usize).unwrap());
+        }
+        provinces.push(province);
+        current_id += 1;
+    }
+}
+
+// An undersized water province goes whole to its smallest sea neighbor with room for it. When every sea neighbor
+// is too full, the smallest one takes it and the two are split in halves, both in one piece.
+// A lake without sea neighbors stays a water province of its own, however small
+fn merge_small_water_province(province: &mut Province, world: &mut World, settings: &Settings, provinces: &mut [Province], random: &mut impl Rng) {
+    let water_neighbors: BTreeMap<u32, u32> = found_province_neighbor(province, world).iter()
+        .filter(|id| !provinces[**id as usize].is_land)
+        .map(|id| (*id, provinces[*id as usize].elements.len() as u32))
+        .collect();
+    let Some(full_id) = smallest_province(&water_neighbors, random) else {
+        return;
+    };
+    let size = province.elements.len() as u32;
+    let with_room: BTreeMap<u32, u32> = water_neighbors.into_iter()
+        .filter(|(_id, length)| length + size <= settings.max_water_province_size)
+        .collect();
+    if let Some(new_province_id) = smallest_province(&with_room, random) {
+        let pixels = std::mem::take(&mut province.elements);
+        give_pixels(provinces, new_province_id, &pixels, world);
+        return;
+    }
+
+    let mut merged = std::mem::take(&mut province.elements);
+    merged.append(&mut provinces[full_id as usize].elements);
+    merged.sort_unstable();
+    for position in halve_cell(world, &mut merged) {
+        province.add_pixel(world, position);
+    }
+    for position in merged {
+        provinces[full_id as usize].add_pixel(world, position);
+    }
+    relink_neighbors(provinces, full_id, world);
+}
+
+// Of equally small provinces a random one
+fn smallest_province(sizes: &BTreeMap<u32, u32>, random: &mut impl Rng) -> Option<u32> {
+    let minimal = *sizes.values().min()?;
+    let possible_province: Vec<&u32> = sizes.iter().filter(|(_id, length)| {
+        **length == minimal
+    }).map(|(id, _length)| id).collect();
+    Some(*possible_province[random.gen_range(0..possible_province.len())])
+}
+
+// Gives the pixels to the province and links it with the provinces around them
+fn give_pixels(provinces: &mut [Province], id: u32, pixels: &[Position], world: &mut World) {
+    let mut around: BTreeSet<u32> = BTreeSet::new();
+    for position in pixels {
+        provinces[id as usize].add_pixel(world, *position);
+        for neighbor in world.neighbors(*position) {
+            around.extend(world.province_id[neighbor].get());
+        }
+    }
+    for neighbor in around {
+        // The province the pixels came from isn't in the list yet
+        if neighbor != id && (neighbor as usize) < provinces.len() && !provinces[id as usize].neighbors.contains(&neighbor) {
+            let (province, neighbor_province) = two_provinces_mut(provinces, id, neighbor);
+            province.add_province_neighbor(neighbor_province);
+        }
+    }
+}
+
+// Fills the coast of every province, land and water provinces must be generated already
+pub fn find_coasts(world: &World, provinces: &mut [Province]) {
+    let is_land: Vec<bool> = provinces.iter().map(|province| province.is_land).collect();
+    for province in provinces.iter_mut() {
+        let mut coastline_length = 0;
+        let mut water_bodies: BTreeSet<u32> = BTreeSet::new();
+        let mut sea_provinces: BTreeSet<u32> = BTreeSet::new();
+        for position in province.elements.iter() {
+            // Sides only, a diagonal neighbor shares no coastline with the pixel
+            for (dx, dy) in world.side_offsets(*position) {
+                let Some(neighbor) = world.step(*position, *dx, *dy) else {
+                    continue;
+                };
+                if world.terrain[neighbor].is_land() == province.is_land {
+                    continue;
+                }
+                coastline_length += 1;
+                if province.is_land {
+                    water_bodies.extend(world.water_body_id[neighbor].get());
+                    sea_provinces.extend(world.province_id[neighbor].get().filter(|id| !is_land[*id as usize]));
+                }
+            }
+        }
+        province.is_coastal = coastline_length > 0;
+        province.coastline_length = coastline_length;
+        province.water_body_ids = water_bodies.into_iter().collect();
+        province.sea_province_ids = sea_provinces.into_iter().collect();
+    }
+}
+
+// Finds the neighbors of the province again after it lost pixels
+fn relink_neighbors(provinces: &mut [Province], id: u32, world: &World) {
+    for neighbor in std::mem::take(&mut provinces[id as usize].neighbors) {
+        provinces[neighbor as usize].neighbors.retain(|other| *other != id);
+    }
+    for neighbor in found_province_neighbor(&provinces[id as usize], world) {
+        // The province the pixels went to isn't in the list yet
+        if (neighbor as usize) < provinces.len() {
+            let (province, neighbor_province) = two_provinces_mut(provinces, id, neighbor);
+            province.add_province_neighbor(neighbor_province);
+        }
+    }
+}
+
+fn two_provinces_mut(provinces: &mut [Province], first: u32, second: u32) -> (&mut Province, &mut Province) {
+    if first < second {
+        let (left, right) = provinces.split_at_mut(second as usize);
+        (&mut left[first as usize], &mut right[0])
+    } else {
+        let (left, right) = provinces.split_at_mut(first as usize);
+        (&mut right[0], &mut left[second as usize])
+    }
+}
+
+// The color is given when the provinces of all continents are merged
+pub fn generate_land_province(id: u32, first_pixel: Position, max_size: u32, map: &mut impl ProvinceMap, continent_id: u32, min_size: u32, random: &mut impl Rng) -> Province {
+    let mut province = Province::new(id, (0, 0, 0), true);
+    province.continent_id = Some(continent_id);
+    grow_province(&mut province, first_pixel, min_size, max_size, map, |world, position| {
+        world.terrain[position].is_land() && world.continent_id[position] == MaybeId::some(continent_id)
+    }, random);
+    province
+}
+
+// Grows the province from first_pixel over the pixels accepted by `belongs`,
+// preferring pixels that already touch the province from several sides.
+// Pixels whose every neighbor that could belong is in the province are added right away
+fn grow_province(province: &mut Province, first_pixel: Position, min_size: u32, max_size: u32, map: &mut impl ProvinceMap, belongs: impl Fn(&World, Position) -> bool, random: &mut impl Rng) {
+    let mut province_size = 0;
+    let mut frontier = WeightedFrontier::new();
+    // Province pixels touching every frontier pixel, and how many of its neighbors could belong to the province
+    let mut touching: HashMap<Position, (u8, u8)> = HashMap::new();
+    let mut anyway_added: Vec<Position> = Vec::new();
+    let mut added_pixels: Vec<Position> = Vec::new();
+    let result_province_size = random.gen_range(((max_size - min_size).max(min_size))..max_size);
+    anyway_added.push(first_pixel);
+    loop {
+        if !anyway_added.is_empty() {
+            anyway_added.sort_unstable();
+            province_size += anyway_added.len() as u32;
+            added_pixels.append(&mut anyway_added);
+        } else if province_size >= result_province_size || frontier.is_empty() {
+            break;
+        } else {
+            added_pixels.extend(frontier.pick(random));
+            province_size += 1;
+        }
+        for position in added_pixels.iter() {
+            province.add_pixel(map, *position);
+            frontier.remove(*position);
+            touching.remove(position);
+        }
+        for position in added_pixels.drain(..) {
+            let world = map.world();
+            for neighbor in world.neighbors(position) {
+                if !belongs(world, neighbor) || map.province_id(neighbor).is_some() {
+                    continue;
+                }
+                let (count, possible) = touching.entry(neighbor).or_insert_with(|| {
+                    (0, world.neighbors(neighbor).into_iter().filter(|around| belongs(world, *around)).count() as u8)
+                });
+                *count += 1;
+                frontier.set(neighbor, (*count + (*count - 1) * 2) as u32);
+                if count == possible {
+                    anyway_added.push(neighbor);
+                }
+            }
+        }
+    }
+}
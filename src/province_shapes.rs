@@ -0,0 +1,334 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+
+use rand::{Rng, seq::SliceRandom};
+use rand_chacha::ChaCha8Rng;
+
+use crate::continent_generator::Continent;
+use crate::province_generator::{average_province_size, found_province_neighbor, ContinentProvinceMap, Province, ProvinceGenerator, ProvinceMap};
+use crate::world::{Grid, MaybeId, Position};
+use crate::{Settings, World};
+
+
+// Share of the plane a saturated Poisson-disk sampling leaves to every seed, in squared radii
+const POISSON_AREA_PER_SEED: f64 = 1.44;
+
+// Seeds at least a radius apart, so that every cell gets the average province size.
+// Every relaxation moves the seeds to the middle of their cells
+pub struct VoronoiProvinces {
+    pub relaxations: u32
+}
+
+impl Default for VoronoiProvinces {
+    fn default() -> Self {
+        VoronoiProvinces { relaxations: 2 }
+    }
+}
+
+impl ProvinceGenerator for VoronoiProvinces {
+    fn continent_provinces(&self, map: &mut ContinentProvinceMap, continent: &Continent, settings: &Settings, random: &mut ChaCha8Rng) -> Vec<Province> {
+        let world = map.world;
+        let cell_area = match world.grid {
+            Grid::Square => 1.0,
+            Grid::Hex => 3f64.sqrt() / 2.0
+        };
+        let radius = (average_province_size(settings) * cell_area / POISSON_AREA_PER_SEED).sqrt();
+        let mut seeds = poisson_disk_seeds(world, &continent.members, radius, random);
+        for _ in 0..self.relaxations {
+            let cells = grow_cells(map, continent, &seeds);
+            seeds = cells.iter().zip(seeds.iter()).map(|(cell, seed)| cell_middle(world, *seed, cell)).collect();
+        }
+        let cells = grow_cells(map, continent, &seeds);
+        let cells = fit_cells(map, cells, settings, random);
+        cells_to_provinces(map, continent, cells)
+    }
+}
+
+// Every province takes one random pixel of its border in turn, until the continent is full
+pub struct FloodProvinces;
+
+impl ProvinceGenerator for FloodProvinces {
+    fn continent_provinces(&self, map: &mut ContinentProvinceMap, continent: &Continent, settings: &Settings, random: &mut ChaCha8Rng) -> Vec<Province> {
+        let world = map.world;
+        let count = ((continent.members.len() as f64 / average_province_size(settings)).round() as usize).clamp(1, continent.members.len());
+        let mut seeds = continent.members.clone();
+        seeds.shuffle(random);
+        seeds.truncate(count);
+        let mut cells: Vec<Vec<Position>> = Vec::with_capacity(count);
+        let mut borders: Vec<Vec<Position>> = Vec::with_capacity(count);
+        for (id, seed) in seeds.iter().enumerate() {
+            map.set_province_id(*seed, id as u32);
+            cells.push(vec![*seed]);
+            borders.push(world.neighbors(*seed).to_vec());
+        }
+        let mut growing = true;
+        while growing {
+            growing = false;
+            for id in 0..count {
+                while !borders[id].is_empty() {
+                    let picked = random.gen_range(0..borders[id].len());
+                    let position = borders[id].swap_remove(picked);
+                    if !in_continent(world, continent, position) || map.province_id(position).is_some() {
+                        continue;
+                    }
+                    map.set_province_id(position, id as u32);
+                    cells[id].push(position);
+                    borders[id].extend(world.neighbors(position));
+                    growing = true;
+                    break;
+                }
+            }
+        }
+        let cells = fit_cells(map, cells, settings, random);
+        cells_to_provinces(map, continent, cells)
+    }
+}
+
+fn in_continent(world: &World, continent: &Continent, position: Position) -> bool {
+    world.continent_id[position] == MaybeId::some(continent.id)
+}
+
+// Center of the cell on a plane where neighbors are one apart, hex rows are closer together
+fn plane(world: &World, position: Position) -> (f64, f64) {
+    match world.grid {
+        Grid::Square => (position.x as f64, position.y as f64),
+        Grid::Hex => (position.x as f64 + world.grid.row_shift(position.y), position.y as f64 * 3f64.sqrt() / 2.0)
+    }
+}
+
+// Shortest step from one cell to another on the plane, across the seams of wrapping maps
+fn step_between(world: &World, from: Position, to: Position) -> (f64, f64) {
+    let (from_x, from_y) = plane(world, from);
+    let (to_x, to_y) = plane(world, to);
+    let (mut dx, mut dy) = (to_x - from_x, to_y - from_y);
+    let (width, height) = (world.width() as f64, plane(world, Position { x: 0, y: world.height() }).1);
+    if world.topology.wraps_east_west() {
+        dx -= width * (dx / width).round();
+    }
+    if world.topology.wraps_north_south() {
+        dy -= height * (dy / height).round();
+    }
+    (dx, dy)
+}
+
+// Dart throwing over the shuffled members, a member becomes a seed when no seed is closer than `radius`.
+// Every member is tried, so the sampling is saturated
+fn poisson_disk_seeds(world: &World, members: &[Position], radius: f64, random: &mut impl Rng) -> Vec<Position> {
+    let mut candidates = members.to_vec();
+    candidates.shuffle(random);
+    let mut seeds: Vec<Position> = Vec::new();
+    // Seeds by the square of side `radius` they are in, so only the squares around a candidate are checked.
+    // Two squares each way, the last square before a seam can be narrower
+    let mut buckets: HashMap<(i64, i64), Vec<Position>> = HashMap::new();
+    let bucket = |position: Position| {
+        let (x, y) = plane(world, position);
+        ((x / radius).floor() as i64, (y / radius).floor() as i64)
+    };
+    let width_buckets = (world.width() as f64 / radius).ceil() as i64;
+    let height_buckets = (plane(world, Position { x: 0, y: world.height() }).1 / radius).ceil() as i64;
+    for candidate in candidates {
+        let (column, row) = bucket(candidate);
+        let mut is_free = true;
+        'search: for dy in -2..=2 {
+            for dx in -2..=2 {
+                let mut around = (column + dx, row + dy);
+                if world.topology.wraps_east_west() {
+                    around.0 = around.0.rem_euclid(width_buckets);
+                }
+                if world.topology.wraps_north_south() {
+                    around.1 = around.1.rem_euclid(height_buckets);
+                }
+                let Some(near) = buckets.get(&around) else {
+                    continue;
+                };
+                if near.iter().any(|seed| {
+                    let (x, y) = step_between(world, candidate, *seed);
+                    x * x + y * y < radius * radius
+                }) {
+                    is_free = false;
+                    break 'search;
+                }
+            }
+        }
+        if is_free {
+            seeds.push(candidate);
+            buckets.entry((column, row)).or_default().push(candidate);
+        }
+    }
+    seeds
+}
+
+// All cells grow at once, always by the free pixel nearest to the seed of the cell reaching it.
+// That's the Voronoi diagram of the seeds where the continent is convex, and the cells still stay connected where it isn't
+fn grow_cells(map: &mut ContinentProvinceMap, continent: &Continent, seeds: &[Position]) -> Vec<Vec<Position>> {
+    let world = map.world;
+    for position in continent.members.iter() {
+        map.clear_province_id(*position);
+    }
+    let mut cells: Vec<Vec<Position>> = vec![Vec::new(); seeds.len()];
+    // Distances are never negative, so their bits sort like the distances, the counter breaks ties in push order
+    let mut queue: BinaryHeap<Reverse<(u64, usize, u32, Position)>> = BinaryHeap::new();
+    let mut pushed = 0;
+    for (id, seed) in seeds.iter().enumerate() {
+        queue.push(Reverse((0, pushed, id as u32, *seed)));
+        pushed += 1;
+    }
+    while let Some(Reverse((_, _, id, position))) = queue.pop() {
+        if map.province_id(position).is_some() {
+            continue;
+        }
+        map.set_province_id(position, id);
+        cells[id as usize].push(position);
+        for neighbor in world.neighbors(position) {
+            if in_continent(world, continent, neighbor) && map.province_id(neighbor).is_none() {
+                let (x, y) = step_between(world, seeds[id as usize], neighbor);
+                queue.push(Reverse(((x * x + y * y).to_bits(), pushed, id, neighbor)));
+                pushed += 1;
+            }
+        }
+    }
+    cells
+}
+
+// The member of the cell nearest to its centroid, so the seed stays inside its cell
+fn cell_middle(world: &World, seed: Position, cell: &[Position]) -> Position {
+    let steps: Vec<(f64, f64)> = cell.iter().map(|position| step_between(world, seed, *position)).collect();
+    let count = steps.len() as f64;
+    let centroid = (steps.iter().map(|step| step.0).sum::<f64>() / count, steps.iter().map(|step| step.1).sum::<f64>() / count);
+    let nearest = steps.iter().enumerate().min_by(|(_, first), (_, second)| {
+        let distance = |step: &(f64, f64)| (step.0 - centroid.0).powi(2) + (step.1 - centroid.1).powi(2);
+        distance(first).total_cmp(&distance(second))
+    }).map_or(0, |(index, _)| index);
+    cell[nearest]
+}
+
+// Merges the cells under min_province_size into their smallest neighbor, like the weighted growth does,
+// then halves the cells over max_province_size until they fit. Cells must already be marked in the map
+pub fn fit_cells(map: &mut ContinentProvinceMap, cells: Vec<Vec<Position>>, settings: &Settings, random: &mut impl Rng) -> Vec<Vec<Position>> {
+    let world = map.world;
+    let mut cells = cells;
+    for id in 0..cells.len() {
+        if cells[id].is_empty() || cells[id].len() as u32 >= settings.min_province_size {
+            continue;
+        }
+        let neighbors: BTreeMap<u32, u32> = cells[id].iter()
+            .flat_map(|position| world.neighbors(*position))
+            .filter_map(|neighbor| map.province_id(neighbor).get())
+            .filter(|neighbor_id| *neighbor_id != id as u32)
+            .map(|neighbor_id| (neighbor_id, cells[neighbor_id as usize].len() as u32))
+            .collect();
+        // A cell without a neighbor stays as it is
+        let Some(minimal) = neighbors.values().min().copied() else {
+            continue;
+        };
+        let possible_cells: Vec<u32> = neighbors.iter().filter(|(_id, length)| **length == minimal).map(|(id, _length)| *id).collect();
+        let new_id = possible_cells[random.gen_range(0..possible_cells.len())];
+        let cell = std::mem::take(&mut cells[id]);
+        for position in cell.iter() {
+            map.set_province_id(*position, new_id);
+        }
+        cells[new_id as usize].extend(cell);
+    }
+    cells.retain(|cell| !cell.is_empty());
+    let mut index = 0;
+    while index < cells.len() {
+        if cells[index].len() as u32 > settings.max_province_size {
+            let half = halve_cell(world, &mut cells[index]);
+            cells.push(half);
+        } else {
+            index += 1;
+        }
+    }
+    for (id, cell) in cells.iter().enumerate() {
+        for position in cell.iter() {
+            map.set_province_id(*position, id as u32);
+        }
+    }
+    cells
+}
+
+// Takes about half of the cell away, cutting across its long side from one of its two far ends.
+// Pieces of the rest cut off from its largest piece go with the taken half, so both halves stay in one piece
+pub fn halve_cell(world: &World, cell: &mut Vec<Position>) -> Vec<Position> {
+    let members: HashSet<Position> = cell.iter().copied().collect();
+    let far_end = *breadth_first(world, &members, cell[0]).last().unwrap();
+    let order = breadth_first(world, &members, far_end);
+    let other_end = *order.last().unwrap();
+    let half = taken_half(world, &members, &order);
+    let other_half = taken_half(world, &members, &breadth_first(world, &members, other_end));
+    let half = if other_half.len() > half.len() { other_half } else { half };
+    cell.retain(|position| !half.contains(position));
+    let mut half: Vec<Position> = half.into_iter().collect();
+    half.sort_unstable();
+    half
+}
+
+// The most pixels from the start of the order that, with the pieces they cut off, are no more than half of the members
+fn taken_half(world: &World, members: &HashSet<Position>, order: &[Position]) -> HashSet<Position> {
+    let (mut low, mut high) = (1, members.len() / 2);
+    let mut half = take_with_stranded(world, members, &order[..low]);
+    while low < high {
+        let taken = (low + high).div_ceil(2);
+        let candidate = take_with_stranded(world, members, &order[..taken]);
+        if candidate.len() * 2 <= members.len() {
+            half = candidate;
+            low = taken;
+        } else {
+            high = taken - 1;
+        }
+    }
+    half
+}
+
+// The taken pixels and every piece of the other members apart from the first of the largest ones
+fn take_with_stranded(world: &World, members: &HashSet<Position>, taken: &[Position]) -> HashSet<Position> {
+    let mut half: HashSet<Position> = taken.iter().copied().collect();
+    let mut rest: HashSet<Position> = members.difference(&half).copied().collect();
+    let mut starts: Vec<Position> = rest.iter().copied().collect();
+    starts.sort_unstable();
+    let mut pieces = Vec::new();
+    for start in starts {
+        if rest.contains(&start) {
+            let piece = breadth_first(world, &rest, start);
+            for position in piece.iter() {
+                rest.remove(position);
+            }
+            pieces.push(piece);
+        }
+    }
+    let kept = pieces.iter().enumerate().max_by_key(|(index, piece)| (piece.len(), Reverse(*index))).map(|(index, _)| index).unwrap();
+    for (index, piece) in pieces.into_iter().enumerate() {
+        if index != kept {
+            half.extend(piece);
+        }
+    }
+    half
+}
+
+// Members reachable from `start`, nearest first
+fn breadth_first(world: &World, members: &HashSet<Position>, start: Position) -> Vec<Position> {
+    let mut order = vec![start];
+    let mut visited: HashSet<Position> = HashSet::from([start]);
+    let mut index = 0;
+    while index < order.len() {
+        for neighbor in world.neighbors(order[index]) {
+            if members.contains(&neighbor) && visited.insert(neighbor) {
+                order.push(neighbor);
+            }
+        }
+        index += 1;
+    }
+    order
+}
+
+// Cells must already be marked in the map, provinces get the cell ids
+pub fn cells_to_provinces(map: &ContinentProvinceMap, continent: &Continent, cells: Vec<Vec<Position>>) -> Vec<Province> {
+    cells.into_iter().enumerate().map(|(id, cell)| {
+        let mut province = Province::new(id as u32, (0, 0, 0), true);
+        province.continent_id = Some(continent.id);
+        province.elements = cell;
+        province.neighbors = found_province_neighbor(&province, map);
+        province.neighbors.sort_unstable();
+        province
+    }).collect()
+}
@@ -0,0 +1,41 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+
+// Every stage reads from its own stream of the same seed,
+// so adding random calls to one stage doesn't shift the others
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Continents = 1,
+    Provinces = 2,
+    WaterProvinces = 3,
+    Climate = 4
+}
+
+#[derive(Clone)]
+pub struct WorldRng {
+    seed: u32
+}
+
+impl WorldRng {
+    pub fn new(seed: u32) -> Self {
+        WorldRng { seed }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn stage(&self, stage: Stage) -> ChaCha8Rng {
+        let mut random = ChaCha8Rng::seed_from_u64(self.seed as u64);
+        random.set_stream(stage as u64);
+        random
+    }
+
+    // Own stream for every item of a stage, like a continent, so items can be generated in any order or at once
+    pub fn item(&self, stage: Stage, item: u32) -> ChaCha8Rng {
+        let mut random = ChaCha8Rng::seed_from_u64(self.seed as u64);
+        random.set_stream(((item as u64 + 1) << 32) | stage as u64);
+        random
+    }
+}
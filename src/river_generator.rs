@@ -0,0 +1,97 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::terrain_generator::sea_level;
+use crate::world::Layer;
+use crate::{Settings, World};
+
+
+pub const MAX_RIVER_WIDTH: u8 = 8;
+
+// Rain that falls on a land pixel before its moisture is added
+const BASE_RAIN: f32 = 0.25;
+
+const NO_PIXEL: u32 = u32::MAX;
+
+// Every land pixel drains into the lowest way out of its basin, found with a priority flood from the water.
+// Pits and flats drain too, so every land pixel has a way to the sea or to a lake.
+// Rain of all pixels upstream gives the discharge, rivers start at highland pixels with enough of it
+// and run downstream until they reach water. Width grows with the log of the discharge
+pub fn generate_rivers(world: &mut World, settings: &Settings) {
+    let area = world.area();
+    let terrain = world.terrain.as_slice();
+    let elevation = world.elevation.as_slice();
+    // Index of the pixel every pixel drains into, NO_PIXEL for water and outlets
+    let mut downstream: Vec<u32> = vec![NO_PIXEL; area];
+    let mut visited = vec![false; area];
+    let mut queue: BinaryHeap<Reverse<(u32, u32)>> = BinaryHeap::new();
+    let has_water = terrain.iter().any(|terrain| !terrain.is_land());
+    for (current, position) in world.positions().enumerate() {
+        // Without any water the rivers leave through the map edges
+        if !terrain[current].is_land() || (!has_water && world.is_edge(position)) {
+            visited[current] = true;
+            queue.push(Reverse((elevation[current].to_bits(), current as u32)));
+        }
+    }
+    // A torus without water has no edges either, everything drains into its lowest pixel
+    if queue.is_empty() {
+        if let Some(lowest) = (0..area).min_by_key(|&current| elevation[current].to_bits()) {
+            visited[lowest] = true;
+            queue.push(Reverse((elevation[lowest].to_bits(), lowest as u32)));
+        }
+    }
+    // Pixels in the order they were reached, every pixel comes after its downstream one
+    let mut order: Vec<u32> = Vec::with_capacity(area);
+    while let Some(Reverse((level, current))) = queue.pop() {
+        order.push(current);
+        for neighbor in world.neighbors(world.position(current as usize)) {
+            let neighbor = world.index(neighbor);
+            if visited[neighbor] {
+                continue;
+            }
+            visited[neighbor] = true;
+            downstream[neighbor] = current;
+            // Pixels inside a pit drain at the level of its rim
+            queue.push(Reverse((elevation[neighbor].to_bits().max(level), neighbor as u32)));
+        }
+    }
+
+    let mut discharge = vec![0.0f32; area];
+    let moisture = world.moisture.as_slice();
+    for &current in order.iter().rev() {
+        let current = current as usize;
+        if terrain[current].is_land() {
+            discharge[current] += BASE_RAIN + moisture[current];
+        }
+        if downstream[current] != NO_PIXEL {
+            discharge[downstream[current] as usize] += discharge[current];
+        }
+    }
+
+    let sea_level = sea_level(world);
+    let land_range = if sea_level < 1.0 { 1.0 - sea_level } else { 1.0 };
+    let min_discharge = settings.river_min_discharge as f32;
+    let mut river_width = vec![0u8; area];
+    for current in 0..area {
+        let is_source = terrain[current].is_land()
+            && river_width[current] == 0
+            && discharge[current] >= min_discharge
+            && ((elevation[current] - sea_level) / land_range) as f64 >= settings.river_source_height;
+        if !is_source {
+            continue;
+        }
+        let mut next = current as u32;
+        // Water ends the river, and a marked pixel means the rest of the way is marked too
+        while next != NO_PIXEL && terrain[next as usize].is_land() && river_width[next as usize] == 0 {
+            river_width[next as usize] = width_for(discharge[next as usize], min_discharge);
+            next = downstream[next as usize];
+        }
+    }
+    world.discharge = Layer::from_values(world.size, discharge);
+    world.river_width = Layer::from_values(world.size, river_width);
+}
+
+fn width_for(discharge: f32, min_discharge: f32) -> u8 {
+    let doublings = (discharge / min_discharge.max(1.0)).log2().max(0.0) as u32;
+    (1 + doublings).min(MAX_RIVER_WIDTH as u32) as u8
+}
@@ -1,21 +1,402 @@
-pub struct Settings {
-    pub world_size: (u32, u32),
-    pub seed: u32,
-    pub min_province_size: u32,
-    pub max_province_size: u32,
-    pub min_water_province_size: u32,
-    pub max_water_province_size: u32
-}
-
-impl Settings {
-    pub fn new(world_size: (u32, u32), seed: u32, max_land_size: u32, min_land_size: u32, max_water_size: u32, min_water_size: u32) -> Self {
-        Settings {
-            seed: seed,
-            world_size: world_size,
-            max_province_size: max_land_size,
-            min_province_size: min_land_size,
-            max_water_province_size: max_water_size,
-            min_water_province_size: min_water_size
-        }
-    }
-}
\ No newline at end of file
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::province_generator::ProvinceShape;
+use crate::terrain_generator::{NoiseKind, NoiseLayer, TerrainBand, TerrainShares};
+use crate::world::{Connectivity, Grid, Terrain, Topology};
+
+
+// Settings file format:
+// one `key = value` pair per line, everything after `#` is a comment,
+// missing keys keep their default value.
+// world_size is written as `width x height`, e.g. `world_size = 1024x1024`,
+// terrain_bands and terrain_shares as comma separated `name:value` pairs.
+// noise_layer can be repeated, one line per layer: the noise kind, then its `name:value` parameters.
+// Layers from the file replace the default ones.
+// temperature_noise and moisture_noise are single layers written the same way
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub world_size: (u32, u32),
+    pub topology: Topology,
+    pub connectivity: Connectivity,
+    pub grid: Grid,
+    pub seed: u32,
+    pub min_province_size: u32,
+    pub max_province_size: u32,
+    pub province_shape: ProvinceShape,
+    pub min_water_province_size: u32,
+    pub max_water_province_size: u32,
+    // Sorted by min_elevation
+    pub terrain_bands: Vec<TerrainBand>,
+    // When set, replaces terrain_bands with bands calibrated for these shares
+    pub terrain_shares: Option<TerrainShares>,
+    pub noise_layers: Vec<NoiseLayer>,
+    pub temperature_noise: NoiseLayer,
+    pub moisture_noise: NoiseLayer,
+    // Rain a highland pixel must gather before a river starts there, one pixel gives up to 1.25
+    pub river_min_discharge: u32,
+    // Lowest start of a river, 0 is the coast and 1 the highest peak
+    pub river_source_height: f64,
+    // Water touching the map edge and covering at least this part of the map is an ocean
+    pub ocean_min_share: f64,
+    // Water enclosed by land and covering at most this part of the map is a lake
+    pub lake_max_share: f64
+}
+
+impl Settings {
+    pub fn new(world_size: (u32, u32), seed: u32, max_land_size: u32, min_land_size: u32, max_water_size: u32, min_water_size: u32) -> Self {
+        Settings {
+            seed,
+            world_size,
+            topology: Topology::Flat,
+            connectivity: Connectivity::VonNeumann,
+            grid: Grid::Square,
+            max_province_size: max_land_size,
+            min_province_size: min_land_size,
+            province_shape: ProvinceShape::Weighted,
+            max_water_province_size: max_water_size,
+            min_water_province_size: min_water_size,
+            terrain_bands: TerrainBand::default_bands(),
+            terrain_shares: None,
+            noise_layers: NoiseLayer::default_layers(),
+            temperature_noise: NoiseLayer::default_temperature(),
+            moisture_noise: NoiseLayer::default_moisture(),
+            river_min_discharge: 300,
+            river_source_height: 0.3,
+            ocean_min_share: 0.1,
+            lake_max_share: 0.002
+        }
+    }
+
+    pub fn from_file(file_name: impl AsRef<Path>) -> Result<Self, SettingsError> {
+        let data = fs::read_to_string(file_name).map_err(SettingsError::Io)?;
+        Settings::parse(&data)
+    }
+
+    pub fn parse(data: &str) -> Result<Self, SettingsError> {
+        let mut settings = Settings::default();
+        let mut noise_layers = Vec::new();
+        for (index, line) in data.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(SettingsError::parse(line_number, format!("expected `key = value`, found {line:?}")));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "world_size" => settings.world_size = parse_size(value).map_err(|message| SettingsError::parse(line_number, message))?,
+                "topology" => settings.topology = Topology::from_name(value).ok_or_else(|| {
+                    SettingsError::parse(line_number, format!("unknown topology {value:?}, expected flat, cylinder or torus"))
+                })?,
+                "connectivity" => settings.connectivity = Connectivity::from_name(value).ok_or_else(|| {
+                    SettingsError::parse(line_number, format!("unknown connectivity {value:?}, expected von_neumann or moore"))
+                })?,
+                "grid" => settings.grid = Grid::from_name(value).ok_or_else(|| {
+                    SettingsError::parse(line_number, format!("unknown grid {value:?}, expected square or hex"))
+                })?,
+                "seed" => settings.seed = parse_number(key, value, line_number)?,
+                "min_province_size" => settings.min_province_size = parse_number(key, value, line_number)?,
+                "max_province_size" => settings.max_province_size = parse_number(key, value, line_number)?,
+                "province_shape" => settings.province_shape = ProvinceShape::from_name(value).ok_or_else(|| {
+                    SettingsError::parse(line_number, format!("unknown province shape {value:?}, expected weighted, voronoi or flood"))
+                })?,
+                "min_water_province_size" => settings.min_water_province_size = parse_number(key, value, line_number)?,
+                "max_water_province_size" => settings.max_water_province_size = parse_number(key, value, line_number)?,
+                "terrain_bands" => settings.terrain_bands = parse_bands(value).map_err(|message| SettingsError::parse(line_number, message))?,
+                "terrain_shares" => settings.terrain_shares = parse_shares(value).map_err(|message| SettingsError::parse(line_number, message))?,
+                "noise_layer" => noise_layers.push(parse_noise_layer(value).map_err(|message| SettingsError::parse(line_number, message))?),
+                "temperature_noise" => settings.temperature_noise = parse_noise_layer(value).map_err(|message| SettingsError::parse(line_number, message))?,
+                "moisture_noise" => settings.moisture_noise = parse_noise_layer(value).map_err(|message| SettingsError::parse(line_number, message))?,
+                "river_min_discharge" => settings.river_min_discharge = parse_number(key, value, line_number)?,
+                "river_source_height" => settings.river_source_height = parse_float(key, value, line_number)?,
+                "ocean_min_share" => settings.ocean_min_share = parse_float(key, value, line_number)?,
+                "lake_max_share" => settings.lake_max_share = parse_float(key, value, line_number)?,
+                _ => return Err(SettingsError::parse(line_number, format!("unknown key {key:?}")))
+            }
+        }
+        if !noise_layers.is_empty() {
+            settings.noise_layers = noise_layers;
+        }
+        settings.check()?;
+        Ok(settings)
+    }
+
+    pub fn check(&self) -> Result<(), SettingsError> {
+        if self.world_size.0 == 0 || self.world_size.1 == 0 {
+            return Err(SettingsError::Invalid(format!("world_size must not be empty, found {}x{}", self.world_size.0, self.world_size.1)));
+        }
+        // Odd rows are shifted, so a seam between two rows of the same kind would tear the hexes apart
+        if self.grid == Grid::Hex && self.topology.wraps_north_south() && self.world_size.1 % 2 == 1 {
+            return Err(SettingsError::Invalid(format!("a hex torus needs an even number of rows, found {}", self.world_size.1)));
+        }
+        if self.min_province_size == 0 || self.min_province_size >= self.max_province_size {
+            return Err(SettingsError::Invalid(format!(
+                "min_province_size ({}) must be above 0 and below max_province_size ({})", self.min_province_size, self.max_province_size
+            )));
+        }
+        if self.min_water_province_size == 0 || self.min_water_province_size >= self.max_water_province_size {
+            return Err(SettingsError::Invalid(format!(
+                "min_water_province_size ({}) must be above 0 and below max_water_province_size ({})", self.min_water_province_size, self.max_water_province_size
+            )));
+        }
+        if self.terrain_bands.is_empty() {
+            return Err(SettingsError::Invalid("terrain_bands must have at least one band".to_string()));
+        }
+        if self.noise_layers.is_empty() || self.noise_layers.iter().map(|layer| layer.weight.abs()).sum::<f64>() == 0.0 {
+            return Err(SettingsError::Invalid("noise layers must have at least one layer with a weight other than 0".to_string()));
+        }
+        for layer in self.noise_layers.iter().chain([&self.temperature_noise, &self.moisture_noise]) {
+            if layer.octaves == 0 || layer.octaves > NoiseLayer::MAX_OCTAVES || layer.frequency <= 0.0 || layer.scale <= 0.0 {
+                return Err(SettingsError::Invalid(format!(
+                    "{} noise layer needs octaves from 1 to {}, frequency and scale above 0", layer.kind.name(), NoiseLayer::MAX_OCTAVES
+                )));
+            }
+        }
+        if self.river_min_discharge == 0 || !(0.0..=1.0).contains(&self.river_source_height) {
+            return Err(SettingsError::Invalid(format!(
+                "river_min_discharge ({}) must be above 0 and river_source_height ({}) between 0 and 1", self.river_min_discharge, self.river_source_height
+            )));
+        }
+        if !(0.0..=1.0).contains(&self.ocean_min_share) || !(0.0..=1.0).contains(&self.lake_max_share) {
+            return Err(SettingsError::Invalid(format!(
+                "ocean_min_share ({}) and lake_max_share ({}) must be between 0 and 1", self.ocean_min_share, self.lake_max_share
+            )));
+        }
+        if let Some(shares) = self.terrain_shares {
+            let in_range = |share: f64| (0.0..=1.0).contains(&share);
+            if !in_range(shares.land) || !in_range(shares.hills) || !in_range(shares.mountains) || shares.hills + shares.mountains > shares.land {
+                return Err(SettingsError::Invalid(format!(
+                    "terrain_shares must be between 0 and 1, with hills and mountains ({} + {}) not above land ({})", shares.hills, shares.mountains, shares.land
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings::new((1024, 1024), 124122, 100, 25, 400, 100)
+    }
+}
+
+// Writes the settings in the same format that `Settings::parse` reads
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# Map generator settings, one `key = value` per line")?;
+        writeln!(f, "world_size = {}x{}", self.world_size.0, self.world_size.1)?;
+        writeln!(f, "# flat, cylinder (wraps east-west) or torus (wraps both ways)")?;
+        writeln!(f, "topology = {}", self.topology.name())?;
+        writeln!(f, "# von_neumann (4 neighbors) or moore (8 neighbors, diagonals too)")?;
+        writeln!(f, "connectivity = {}", self.connectivity.name())?;
+        writeln!(f, "# square or hex, hexes always have 6 neighbors")?;
+        writeln!(f, "grid = {}", self.grid.name())?;
+        writeln!(f, "seed = {}", self.seed)?;
+        writeln!(f, "min_province_size = {}", self.min_province_size)?;
+        writeln!(f, "max_province_size = {}", self.max_province_size)?;
+        writeln!(f, "# weighted, voronoi or flood")?;
+        writeln!(f, "province_shape = {}", self.province_shape.name())?;
+        writeln!(f, "min_water_province_size = {}", self.min_water_province_size)?;
+        writeln!(f, "max_water_province_size = {}", self.max_water_province_size)?;
+        writeln!(f, "# Bands as `terrain:lowest elevation`, the elevation is about -1 to 1 and the highest fitting band wins")?;
+        let bands: Vec<String> = self.terrain_bands.iter().map(|band| format!("{}:{}", band.terrain.name(), band.min_elevation)).collect();
+        writeln!(f, "terrain_bands = {}", bands.join(", "))?;
+        writeln!(f, "# Wanted shares of the map, replace terrain_bands with bands picked for every seed")?;
+        match self.terrain_shares {
+            Some(shares) => writeln!(f, "terrain_shares = land:{}, hills:{}, mountains:{}", shares.land, shares.hills, shares.mountains)?,
+            None => writeln!(f, "# terrain_shares = land:0.6, hills:0.1, mountains:0.05")?
+        }
+        writeln!(f, "# Noise kinds: {}", NoiseKind::ALL.map(|kind| kind.name()).join(", "))?;
+        for layer in self.noise_layers.iter() {
+            writeln!(f, "noise_layer = {}", format_noise_layer(layer))?;
+        }
+        writeln!(f, "# Temperature noise weight is how far it moves the 0..1 temperature, moisture is scaled to 0..1")?;
+        writeln!(f, "temperature_noise = {}", format_noise_layer(&self.temperature_noise))?;
+        writeln!(f, "moisture_noise = {}", format_noise_layer(&self.moisture_noise))?;
+        writeln!(f, "# Rivers start at pixels above river_source_height (0 is the coast, 1 the highest peak) that gather enough rain")?;
+        writeln!(f, "river_min_discharge = {}", self.river_min_discharge)?;
+        writeln!(f, "river_source_height = {}", self.river_source_height)?;
+        writeln!(f, "# Parts of the map: open water from ocean_min_share is an ocean, enclosed water up to lake_max_share a lake, the rest seas")?;
+        writeln!(f, "ocean_min_share = {}", self.ocean_min_share)?;
+        writeln!(f, "lake_max_share = {}", self.lake_max_share)?;
+        Ok(())
+    }
+}
+
+fn format_noise_layer(layer: &NoiseLayer) -> String {
+    format!(
+        "{}, weight:{}, octaves:{}, frequency:{}, lacunarity:{}, persistence:{}, scale:{}",
+        layer.kind.name(), layer.weight, layer.octaves, layer.frequency, layer.lacunarity, layer.persistence, layer.scale
+    )
+}
+
+fn parse_number(key: &str, value: &str, line_number: usize) -> Result<u32, SettingsError> {
+    value.parse().map_err(|_| SettingsError::parse(line_number, format!("{key} must be a positive integer, found {value:?}")))
+}
+
+fn parse_float(key: &str, value: &str, line_number: usize) -> Result<f64, SettingsError> {
+    value.parse().map_err(|_| SettingsError::parse(line_number, format!("{key} must be a number, found {value:?}")))
+}
+
+pub fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let error = || format!("world_size must look like `1024x1024`, found {value:?}");
+    let (width, height) = value.split_once('x').ok_or_else(error)?;
+    let width = width.trim().parse().map_err(|_| error())?;
+    let height = height.trim().parse().map_err(|_| error())?;
+    Ok((width, height))
+}
+
+// `name:value, name:value`
+fn parse_pairs(value: &str) -> Result<Vec<(&str, f64)>, String> {
+    value.split(',').map(|pair| {
+        let (name, number) = pair.split_once(':').ok_or_else(|| format!("expected `name:number`, found {:?}", pair.trim()))?;
+        let number = number.trim().parse().map_err(|_| format!("{:?} is not a number", number.trim()))?;
+        Ok((name.trim(), number))
+    }).collect()
+}
+
+fn parse_bands(value: &str) -> Result<Vec<TerrainBand>, String> {
+    let mut bands = parse_pairs(value)?.into_iter().map(|(name, min_elevation)| {
+        let terrain = Terrain::from_name(name).ok_or_else(|| format!("unknown terrain {name:?}"))?;
+        Ok(TerrainBand::new(terrain, min_elevation))
+    }).collect::<Result<Vec<TerrainBand>, String>>()?;
+    bands.sort_by(|a, b| a.min_elevation.total_cmp(&b.min_elevation));
+    Ok(bands)
+}
+
+fn parse_shares(value: &str) -> Result<Option<TerrainShares>, String> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let mut shares = TerrainShares { land: 0.0, hills: 0.0, mountains: 0.0 };
+    for (name, share) in parse_pairs(value)? {
+        match name {
+            "land" => shares.land = share,
+            "hills" => shares.hills = share,
+            "mountains" => shares.mountains = share,
+            _ => return Err(format!("unknown share {name:?}, expected land, hills or mountains"))
+        }
+    }
+    Ok(Some(shares))
+}
+
+// `kind, name:value, name:value`, missing parameters keep the defaults of the kind
+fn parse_noise_layer(value: &str) -> Result<NoiseLayer, String> {
+    let (kind, parameters) = value.split_once(',').unwrap_or((value, ""));
+    let kind = NoiseKind::from_name(kind.trim()).ok_or_else(|| format!("unknown noise {:?}", kind.trim()))?;
+    let mut layer = NoiseLayer::new(kind);
+    if parameters.trim().is_empty() {
+        return Ok(layer);
+    }
+    for (name, number) in parse_pairs(parameters)? {
+        match name {
+            "weight" => layer.weight = number,
+            "octaves" => layer.octaves = number as usize,
+            "frequency" => layer.frequency = number,
+            "lacunarity" => layer.lacunarity = number,
+            "persistence" => layer.persistence = number,
+            "scale" => layer.scale = number,
+            _ => return Err(format!("unknown noise parameter {name:?}"))
+        }
+    }
+    Ok(layer)
+}
+
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+    Invalid(String)
+}
+
+impl SettingsError {
+    fn parse(line: usize, message: String) -> Self {
+        SettingsError::Parse { line, message }
+    }
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Io(err) => write!(f, "can't read settings: {err}"),
+            SettingsError::Parse { line, message } => write!(f, "settings line {line}: {message}"),
+            SettingsError::Invalid(message) => write!(f, "invalid settings: {message}")
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error_line(data: &str) -> (usize, String) {
+        match Settings::parse(data) {
+            Err(SettingsError::Parse { line, message }) => (line, message),
+            other => panic!("expected a parse error, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn unknown_key_is_an_error() {
+        let (line, message) = parse_error_line("seed = 1\nsea_level = 3\n");
+        assert_eq!(line, 2);
+        assert!(message.contains("sea_level"), "{message}");
+    }
+
+    #[test]
+    fn bad_values_report_their_line() {
+        let data = "# settings\n\nworld_size = 64x64\nseed = -4\n";
+        let (line, message) = parse_error_line(data);
+        assert_eq!(line, 4);
+        assert!(message.contains("seed"), "{message}");
+        assert_eq!(parse_error_line("topology = sphere").0, 1);
+        assert_eq!(parse_error_line("seed = 1\nworld_size = 64 by 64").0, 2);
+        assert_eq!(parse_error_line("\n\n\nnoise_layer = perlin, weight:heavy").0, 4);
+        assert_eq!(parse_error_line("seed 1").0, 1);
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        let data = "# the seed\n   # indented comment\nseed = 77 # trailing comment\n#seed = 5\n";
+        let settings = Settings::parse(data).unwrap();
+        assert_eq!(settings.seed, 77);
+    }
+
+    #[test]
+    fn missing_keys_keep_defaults() {
+        assert_eq!(Settings::parse("").unwrap(), Settings::default());
+        let settings = Settings::parse("seed = 9\ngrid = hex\n").unwrap();
+        assert_eq!(settings, Settings { seed: 9, grid: Grid::Hex, ..Settings::default() });
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let settings = Settings::default();
+        assert_eq!(Settings::parse(&settings.to_string()).unwrap(), settings);
+
+        let mut layer = NoiseLayer::new(NoiseKind::Worley);
+        layer.weight = 0.35;
+        layer.octaves = 3;
+        let settings = Settings {
+            world_size: (640, 480),
+            topology: Topology::Torus,
+            connectivity: Connectivity::Moore,
+            grid: Grid::Hex,
+            seed: 4_000_000_000,
+            province_shape: ProvinceShape::Voronoi,
+            terrain_shares: Some(TerrainShares { land: 0.55, hills: 0.1, mountains: 0.03 }),
+            noise_layers: vec![NoiseLayer::new(NoiseKind::Fbm), layer],
+            river_source_height: 0.125,
+            lake_max_share: 0.0015,
+            ..Settings::default()
+        };
+        assert_eq!(Settings::parse(&settings.to_string()).unwrap(), settings);
+    }
+}
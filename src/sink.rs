@@ -0,0 +1,60 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::MapGenError;
+use crate::utils::{adjacency_to_files, biome_to_image, continent_to_image, coordinates_to_file, heightmap_to_image, metrics_to_file, province_to_image, river_to_image, water_body_to_image, world_to_image, MapImage};
+use crate::world::World;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GenerationStage {
+    Terrain,
+    Continents,
+    Provinces
+}
+
+// Receives the world after every stage.
+// Generation itself never touches the filesystem, sinks and the logger are the only way out
+pub trait Sink {
+    fn stage(&mut self, stage: GenerationStage, world: &World) -> Result<(), MapGenError>;
+}
+
+// Writes the chosen stage images into `dir`, creating it when needed
+pub struct ImageSink {
+    dir: PathBuf,
+    images: Vec<MapImage>
+}
+
+impl ImageSink {
+    pub fn new(dir: impl Into<PathBuf>, images: Vec<MapImage>) -> Self {
+        ImageSink { dir: dir.into(), images }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn images(&self) -> &[MapImage] {
+        &self.images
+    }
+}
+
+impl Sink for ImageSink {
+    fn stage(&mut self, stage: GenerationStage, world: &World) -> Result<(), MapGenError> {
+        for image in self.images.iter().filter(|image| image.stage() == stage) {
+            std::fs::create_dir_all(&self.dir).map_err(|err| MapGenError::io(&self.dir, err))?;
+            match image {
+                MapImage::Terrain => world_to_image(world, &self.dir)?,
+                MapImage::Heightmap => heightmap_to_image(world, &self.dir)?,
+                MapImage::Biomes => biome_to_image(world, &self.dir)?,
+                MapImage::Rivers => river_to_image(world, &self.dir)?,
+                MapImage::WaterBodies => water_body_to_image(world, &self.dir)?,
+                MapImage::Continents => continent_to_image(world, &self.dir)?,
+                MapImage::Provinces => province_to_image(world, &self.dir)?,
+                MapImage::Coordinates => coordinates_to_file(world, &self.dir)?,
+                MapImage::Adjacency => adjacency_to_files(world, &self.dir)?,
+                MapImage::Metrics => metrics_to_file(world, &self.dir)?
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+use std::f64::consts::PI;
+
+use noise::{self, Billow, Fbm, MultiFractal, NoiseFn, OpenSimplex, Perlin, RidgedMulti, Seedable, Worley};
+
+use crate::world::{Grid, Layer, Position, Topology};
+use crate::{Settings, Terrain, World};
+
+
+// Every pixel with elevation at or above `min_elevation` gets this terrain,
+// unless a band with a higher `min_elevation` fits too.
+// Pixels below every band get the terrain of the lowest one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainBand {
+    pub terrain: Terrain,
+    pub min_elevation: f64
+}
+
+impl TerrainBand {
+    pub fn new(terrain: Terrain, min_elevation: f64) -> Self {
+        TerrainBand { terrain, min_elevation }
+    }
+
+    pub fn default_bands() -> Vec<TerrainBand> {
+        vec![
+            TerrainBand::new(Terrain::Water, -1.0),
+            TerrainBand::new(Terrain::Plains, -0.75),
+            TerrainBand::new(Terrain::Hills, 0.7),
+            TerrainBand::new(Terrain::Mountains, 0.8)
+        ]
+    }
+}
+
+// Wanted parts of the whole map, hills and mountains are counted into land too
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainShares {
+    pub land: f64,
+    pub hills: f64,
+    pub mountains: f64
+}
+
+impl TerrainShares {
+    // Picks the band elevations from the noise itself, so every seed gets the same shares
+    pub fn calibrate(&self, elevations: &[f64]) -> Vec<TerrainBand> {
+        let mut sorted = elevations.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let quantile = |share: f64| {
+            let position = (sorted.len() as f64 * (1.0 - share)) as usize;
+            sorted.get(position).copied().unwrap_or(f64::INFINITY)
+        };
+        vec![
+            TerrainBand::new(Terrain::Water, f64::NEG_INFINITY),
+            TerrainBand::new(Terrain::Plains, quantile(self.land)),
+            TerrainBand::new(Terrain::Hills, quantile(self.hills + self.mountains)),
+            TerrainBand::new(Terrain::Mountains, quantile(self.mountains))
+        ]
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseKind {
+    Perlin,
+    OpenSimplex,
+    Worley,
+    Fbm,
+    RidgedMulti,
+    Billow
+}
+
+impl NoiseKind {
+    pub const ALL: [NoiseKind; 6] = [NoiseKind::Perlin, NoiseKind::OpenSimplex, NoiseKind::Worley, NoiseKind::Fbm, NoiseKind::RidgedMulti, NoiseKind::Billow];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoiseKind::Perlin => "perlin",
+            NoiseKind::OpenSimplex => "open_simplex",
+            NoiseKind::Worley => "worley",
+            NoiseKind::Fbm => "fbm",
+            NoiseKind::RidgedMulti => "ridged_multi",
+            NoiseKind::Billow => "billow"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        NoiseKind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+// One noise function of the terrain. Octaves, lacunarity and persistence only
+// matter for the fractal kinds (fbm, ridged_multi, billow), frequency for all of them.
+// Scale stretches the map over more of the noise, so bigger scale means smaller features
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseLayer {
+    pub kind: NoiseKind,
+    pub weight: f64,
+    pub octaves: usize,
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub persistence: f64,
+    pub scale: f64
+}
+
+impl NoiseLayer {
+    pub const MAX_OCTAVES: usize = Fbm::<Perlin>::MAX_OCTAVES;
+
+    pub fn new(kind: NoiseKind) -> Self {
+        let persistence = match kind {
+            NoiseKind::RidgedMulti => RidgedMulti::<Perlin>::DEFAULT_PERSISTENCE,
+            _ => Fbm::<Perlin>::DEFAULT_PERSISTENCE
+        };
+        NoiseLayer {
+            kind,
+            weight: 1.0,
+            octaves: Fbm::<Perlin>::DEFAULT_OCTAVE_COUNT,
+            frequency: Fbm::<Perlin>::DEFAULT_FREQUENCY,
+            lacunarity: Fbm::<Perlin>::DEFAULT_LACUNARITY,
+            persistence,
+            scale: 1.0
+        }
+    }
+
+    pub fn default_layers() -> Vec<NoiseLayer> {
+        vec![NoiseLayer::new(NoiseKind::Billow)]
+    }
+
+    // Its weight is how far the noise can move the 0..1 temperature away from the latitude
+    pub fn default_temperature() -> NoiseLayer {
+        NoiseLayer { weight: 0.15, scale: 2.0, ..NoiseLayer::new(NoiseKind::Fbm) }
+    }
+
+    // Scaled to 0..1 afterwards, so the weight doesn't matter
+    pub fn default_moisture() -> NoiseLayer {
+        NoiseLayer { scale: 2.0, ..NoiseLayer::new(NoiseKind::Fbm) }
+    }
+
+    // `Source` is the gradient noise under perlin and the fractal kinds
+    fn build<const D: usize, Source>(&self, seed: u32) -> Box<dyn NoiseFn<f64, D>>
+    where
+        Source: NoiseFn<f64, D> + Seedable + Default + 'static,
+        OpenSimplex: NoiseFn<f64, D>,
+        Worley: NoiseFn<f64, D>,
+        Fbm<Source>: NoiseFn<f64, D>,
+        RidgedMulti<Source>: NoiseFn<f64, D>,
+        Billow<Source>: NoiseFn<f64, D>
+    {
+        match self.kind {
+            NoiseKind::Perlin => Box::new(Source::default().set_seed(seed)),
+            NoiseKind::OpenSimplex => Box::new(OpenSimplex::new(seed)),
+            NoiseKind::Worley => Box::new(Worley::new(seed).set_frequency(self.frequency)),
+            NoiseKind::Fbm => Box::new(self.fractal(Fbm::<Source>::new(seed))),
+            NoiseKind::RidgedMulti => Box::new(self.fractal(RidgedMulti::<Source>::new(seed))),
+            NoiseKind::Billow => Box::new(self.fractal(Billow::<Source>::new(seed)))
+        }
+    }
+
+    fn fractal<T: MultiFractal>(&self, noise: T) -> T {
+        noise.set_octaves(self.octaves)
+            .set_frequency(self.frequency)
+            .set_lacunarity(self.lacunarity)
+            .set_persistence(self.persistence)
+    }
+
+    // Fractals and worley apply the frequency themselves
+    fn point_scale(&self) -> f64 {
+        match self.kind {
+            NoiseKind::Perlin | NoiseKind::OpenSimplex => self.scale * self.frequency,
+            _ => self.scale
+        }
+    }
+}
+
+
+// Weighted sum of all layers, divided by the sum of weights to stay in the range of one layer.
+// Values go row by row, the map covers -1..1 of the noise on both axes.
+// Wrapped axes are sampled around a circle instead of along a line, so the noise meets itself at the seam.
+// Hexes are sampled at their centers, so a hex map has the same geography as a square one
+pub fn generate_noise(seed: u32, size: (u32, u32), layers: &[NoiseLayer], topology: Topology, grid: Grid) -> Vec<f64> {
+    // The circle is as long as the flat axis, so features keep their size
+    let radius = 1.0 / PI;
+    let around = |value: f64| {
+        let (sin, cos) = (value * PI).sin_cos();
+        (cos * radius, sin * radius)
+    };
+    match topology {
+        Topology::Flat => sample_noise::<2, Perlin>(seed, size, layers, grid, |x, y| [x, y]),
+        Topology::Cylinder => sample_noise::<3, Perlin>(seed, size, layers, grid, |x, y| {
+            let (x_cos, x_sin) = around(x);
+            [x_cos, x_sin, y]
+        }),
+        // 4D perlin of the noise crate jumps at its cell borders, open simplex stays smooth
+        Topology::Torus => sample_noise::<4, OpenSimplex>(seed, size, layers, grid, |x, y| {
+            let (x_cos, x_sin) = around(x);
+            let (y_cos, y_sin) = around(y);
+            [x_cos, x_sin, y_cos, y_sin]
+        })
+    }
+}
+
+fn sample_noise<const D: usize, Source>(seed: u32, size: (u32, u32), layers: &[NoiseLayer], grid: Grid, point: impl Fn(f64, f64) -> [f64; D]) -> Vec<f64>
+where
+    Source: NoiseFn<f64, D> + Seedable + Default + 'static,
+    OpenSimplex: NoiseFn<f64, D>,
+    Worley: NoiseFn<f64, D>,
+    Fbm<Source>: NoiseFn<f64, D>,
+    RidgedMulti<Source>: NoiseFn<f64, D>,
+    Billow<Source>: NoiseFn<f64, D>
+{
+    let noises: Vec<(Box<dyn NoiseFn<f64, D>>, f64, f64)> = layers.iter().enumerate()
+        .map(|(index, layer)| (layer.build::<D, Source>(seed.wrapping_add(index as u32)), layer.weight, layer.point_scale()))
+        .collect();
+    let total_weight: f64 = layers.iter().map(|layer| layer.weight.abs()).sum();
+    let (width, height) = (size.0 as usize, size.1 as usize);
+    let x_step = 2.0 / width as f64;
+    let y_step = 2.0 / height as f64;
+    let mut values = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let current_y = -1.0 + y_step * y as f64;
+        for x in 0..width {
+            let current_x = -1.0 + x_step * (x as f64 + grid.row_shift(y as u32));
+            let point = point(current_x, current_y);
+            let value: f64 = noises.iter()
+                .map(|(noise, weight, scale)| weight * noise.get(point.map(|coordinate| coordinate * scale)))
+                .sum();
+            values.push(value / total_weight);
+        }
+    }
+    values
+}
+
+// Bands must be sorted by `min_elevation`
+pub fn terrain_for(bands: &[TerrainBand], elevation: f64) -> Terrain {
+    bands.iter().rev()
+        .find(|band| elevation >= band.min_elevation)
+        .or(bands.first())
+        .map(|band| band.terrain)
+        .unwrap_or(Terrain::Plains)
+}
+
+pub fn generate_map(settings: &Settings) -> World {
+    let terrain_map = generate_noise(settings.seed, settings.world_size, &settings.noise_layers, settings.topology, settings.grid);
+    let bands = match settings.terrain_shares {
+        Some(shares) => shares.calibrate(&terrain_map),
+        None => settings.terrain_bands.clone()
+    };
+    let lowest = terrain_map.iter().copied().fold(f64::INFINITY, f64::min);
+    let highest = terrain_map.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let elevation_range = if highest > lowest { highest - lowest } else { 1.0 };
+    let size = settings.world_size;
+    let mut world = World::new(settings.seed, size, settings.topology, settings.connectivity, settings.grid);
+    let terrain: Vec<Terrain> = terrain_map.iter().map(|value| terrain_for(&bands, *value)).collect();
+    world.elevation = Layer::from_values(size, terrain_map.iter().map(|value| ((value - lowest) / elevation_range) as f32).collect());
+    world.biome = Layer::from_values(size, terrain.clone());
+    world.terrain = Layer::from_values(size, terrain);
+    world
+}
+
+// Elevation of the lowest land pixel, 1 when there is no land
+pub fn sea_level(world: &World) -> f32 {
+    world.terrain.iter().zip(world.elevation.iter())
+        .filter(|(terrain, _)| terrain.is_land())
+        .map(|(_, elevation)| *elevation)
+        .fold(1.0, f32::min)
+}
+
+// Land pixels sharing a side with water, the same sides find_coasts counts. Diagonals don't count with moore connectivity either
+pub fn mark_coastal_pixels(world: &mut World) {
+    let coastal_pixels: Vec<Position> = world.positions()
+        .filter(|position| world.terrain[*position].is_land())
+        .filter(|position| world.side_offsets(*position).iter()
+            .filter_map(|(dx, dy)| world.step(*position, *dx, *dy))
+            .any(|neighbor| !world.terrain[neighbor].is_land()))
+        .collect();
+    for position in coastal_pixels {
+        world.is_coastal[position] = true;
+    }
+}
+
+// Lone water pixels would become sea provinces of one pixel, so fill them with land
+pub fn delete_single_water_pixel(world: &mut World) {
+    let water_pixels: Vec<Position> = world.positions().filter(|position| !world.terrain[*position].is_land()).collect();
+    for position in water_pixels {
+        if world.neighbors(position).iter().all(|neighbor| world.terrain[*neighbor].is_land()) {
+            world.terrain[position] = Terrain::Plains;
+        }
+    }
+}
@@ -1,145 +1,336 @@
-use std::fs::OpenOptions;
-use std::io::Write;
-
-use crate::world::World;
-use crate::START_TIME;
-
-
-pub fn world_to_image(world: &World) {
-    let mut colors: Vec<u8> = Vec::new();
-    for row in world.pixels.iter() {
-        let colors_row = row.iter().map(|pixel| pixel.terrain.to_color());
-        for pixel in colors_row {
-            colors.extend(pixel);
-        }
-    }
-    buffer_to_image(&format!("logs/{}/maps/terrain.png", START_TIME.as_str()), colors, world.size);
-}
-
-pub fn continent_to_image(world: &World) {
-    let mut continents_image: Vec<u8> = Vec::new();
-    for i  in world.pixels.iter() {
-        for j in i.iter() {
-            match j.continent_id {
-                Some(id) => {
-                    let color = world.continents.get(id as usize).unwrap().color;
-                    continents_image.extend(&mut vec![color.0, color.1, color.2].iter());
-                },
-                None => {
-                    continents_image.extend(vec![0, 0, 0]);
-                }
-            }
-        }
-    }
-    buffer_to_image(&format!("logs/{}/maps/continents.png", START_TIME.as_str()), continents_image, world.size);
-}
-
-pub fn province_to_image(world: &World) {
-    let mut buff = Vec::new();
-    for i  in world.pixels.iter() {
-        for j in i.iter() {
-            match j.province_id {
-                Some(id) => {
-                    let province_color = world.provinces.get(id as usize).unwrap().color;
-                    buff.extend(&mut vec![province_color.0, province_color.1, province_color.2].iter());
-                },
-                None => {
-                    buff.extend(vec![0, 0, 0]);
-                }
-            }
-        }
-    }
-    buffer_to_image(&format!("logs/{}/maps/provinces.png", START_TIME.as_str()), buff, world.size)
-}
-
-pub fn resource_to_image(world: &World, resource: &str) {
-    let mut buff = Vec::new();
-    for i  in world.pixels.iter() {
-        for j in i.iter() {
-            match j.province_id {
-                Some(id) => {
-                    let resource: u8 = (*world.provinces.get(id as usize).unwrap().resources.get(resource).unwrap()).clamp(0, 255) as u8;
-                    buff.extend(&mut vec![0, resource, 0].iter());
-                },
-                None => {
-                    buff.extend(vec![0, 0, 0]);
-                }
-            }
-        }
-    }
-    buffer_to_image(&format!("logs/{}/maps/{resource}.png", START_TIME.as_str()), buff, world.size)
-}
-
-pub fn buffer_to_image(result_file_name: &str, buff: Vec<u8>, size: (u32, u32)) {
-    image::save_buffer(
-        result_file_name,
-        &buff,
-        size.0 as u32,
-        size.1 as u32,
-        image::ColorType::Rgb8)
-        .expect("");
-}
-
-pub fn log(data: &str) {
-    let data = format!("{}\n", data);
-    let mut log_file = OpenOptions::new()
-        .write(true)
-        .append(true)
-        .open(format!("logs/{}/log.txt", START_TIME.as_str()))
-        .unwrap();
-    log_file.write_all(data.as_bytes()).unwrap();
-}
-
-pub fn generate_colors() -> Vec<(u8, u8, u8)> {
-    let mut colors: Vec<(u8, u8, u8)> = Vec::new();
-    for i in 0..50 {
-        for j in 0..50 {
-            for t in 0..50 {
-                colors.push((30 + i * 4, 30 + j * 4, 30 + t * 4))
-            }
-        }
-    }
-    return colors
-}
-
-pub mod necessary {
-    use std::path::Path;
-    use std::fs::{create_dir, File};
-
-    use crate::{log, START_TIME};
-
-    // By default - create necessary files/dirs and log creation
-    // TODO: Make in settings some options for this
-    pub fn check_necessary_files() {
-        let log_dir = START_TIME.as_str();
-        check_dir("logs");
-        check_dir(&format!("logs/{}/", log_dir));
-        check_file(&format!("logs/{}/log.txt", log_dir));
-        check_dir(&format!("logs/{}/maps/", log_dir));
-        check_dir("gamedata");
-        check_file("gamedata/settings.txt");
-    }
-
-    fn check_dir(dir_name: &str) {
-        if !Path::new(dir_name).exists() {
-            match create_dir(dir_name) {
-                Ok(_) => {},
-                Err(err) => {panic!("{:?}\n{:?}", err, dir_name)}
-            };
-        }
-    }
-    
-    fn check_file(file_name: &str) {
-        if !Path::new(file_name).exists() {
-            create_file(file_name);
-            log(format!("[Info] {:?} - created", file_name).as_str());
-        }
-    }
-    
-    fn create_file(file_name: &str) {
-        match File::create(file_name) {
-            Ok(_) => {},
-            Err(err) => {log(format!("[Error] File {:?} - {:?}", file_name, err).as_str())}
-        };
-    }
-}
\ No newline at end of file
+use std::fmt::Write as _;
+use std::path::Path;
+
+use image::{ImageBuffer, Luma};
+
+use crate::error::MapGenError;
+use crate::metrics::metrics_to_csv;
+use crate::settings::Settings;
+use crate::sink::GenerationStage;
+use crate::world::{Grid, Position, World};
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapImage {
+    Terrain,
+    // 16-bit png and raw r16 of the elevation
+    Heightmap,
+    // Terrain with the climate biomes
+    Biomes,
+    // Rivers drawn over the terrain, wider where more water flows
+    Rivers,
+    // Oceans, seas and lakes in their own shades
+    WaterBodies,
+    Continents,
+    Provinces,
+    // coordinates.csv with the offset, axial and image coordinates of every cell
+    Coordinates,
+    // adjacency.csv and adjacency.json with the border and kind of every pair of touching provinces
+    Adjacency,
+    // metrics.csv with the area, centroid, label anchor, box, perimeter and compactness of every continent and province
+    Metrics
+}
+
+impl MapImage {
+    pub const ALL: [MapImage; 10] = [
+        MapImage::Terrain, MapImage::Heightmap, MapImage::Biomes, MapImage::Rivers, MapImage::WaterBodies,
+        MapImage::Continents, MapImage::Provinces, MapImage::Coordinates, MapImage::Adjacency, MapImage::Metrics
+    ];
+
+    // Only the images, the coordinates are a big file most runs don't need
+    pub const DEFAULT: [MapImage; 7] = [
+        MapImage::Terrain, MapImage::Heightmap, MapImage::Biomes, MapImage::Rivers,
+        MapImage::WaterBodies, MapImage::Continents, MapImage::Provinces
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MapImage::Terrain => "terrain",
+            MapImage::Heightmap => "heightmap",
+            MapImage::Biomes => "biomes",
+            MapImage::Rivers => "rivers",
+            MapImage::WaterBodies => "water_bodies",
+            MapImage::Continents => "continents",
+            MapImage::Provinces => "provinces",
+            MapImage::Coordinates => "coordinates",
+            MapImage::Adjacency => "adjacency",
+            MapImage::Metrics => "metrics"
+        }
+    }
+
+    // The stage after which the image can be drawn
+    pub fn stage(&self) -> GenerationStage {
+        match self {
+            MapImage::Terrain | MapImage::Heightmap | MapImage::Biomes | MapImage::Rivers | MapImage::WaterBodies | MapImage::Coordinates => GenerationStage::Terrain,
+            MapImage::Continents => GenerationStage::Continents,
+            MapImage::Provinces | MapImage::Adjacency | MapImage::Metrics => GenerationStage::Provinces
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        MapImage::ALL.into_iter().find(|image| image.name() == name)
+    }
+}
+
+pub fn world_to_image(world: &World, dir: &Path) -> Result<(), MapGenError> {
+    let colors: Vec<u8> = world.terrain.iter().flat_map(|terrain| terrain.to_color()).collect();
+    cells_to_image(world, &dir.join("terrain.png"), colors)
+}
+
+pub fn biome_to_image(world: &World, dir: &Path) -> Result<(), MapGenError> {
+    let colors: Vec<u8> = world.biome.iter().flat_map(|biome| biome.to_color()).collect();
+    cells_to_image(world, &dir.join("biomes.png"), colors)
+}
+
+// A river of width w covers the pixels within (w - 1) / 2 of its middle
+pub fn river_to_image(world: &World, dir: &Path) -> Result<(), MapGenError> {
+    let mut colors: Vec<u8> = world.terrain.iter().flat_map(|terrain| terrain.to_color()).collect();
+    let (width, height) = (world.width() as i64, world.height() as i64);
+    for position in world.positions().filter(|position| world.river_width[*position] > 0) {
+        let radius = (world.river_width[position] as i64 - 1) / 2;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let (x, y) = (position.x as i64 + dx, position.y as i64 + dy);
+                if dx * dx + dy * dy > radius * radius || x < 0 || y < 0 || x >= width || y >= height {
+                    continue;
+                }
+                let start = ((y * width + x) * 3) as usize;
+                colors[start..start + 3].copy_from_slice(&[20, 60, 200]);
+            }
+        }
+    }
+    cells_to_image(world, &dir.join("rivers.png"), colors)
+}
+
+// Every water body in the color of its kind, a bit lighter or darker by id so neighbors of one kind stay apart
+pub fn water_body_to_image(world: &World, dir: &Path) -> Result<(), MapGenError> {
+    let colors: Vec<u8> = world.water_body_id.iter().flat_map(|id| match id.get() {
+        Some(id) => {
+            let color = world.water_bodies[id as usize].kind.to_color();
+            let shade = (id * 37 % 41) as i32 - 20;
+            [color.0, color.1, color.2].map(|channel| (channel as i32 + shade).clamp(0, 255) as u8)
+        },
+        None => [0, 0, 0]
+    }).collect();
+    cells_to_image(world, &dir.join("water_bodies.png"), colors)
+}
+
+// Writes heightmap.png as 16-bit grayscale and heightmap.r16 as raw little-endian u16,
+// both row by row, 0 is the lowest point of the map and 65535 the highest
+pub fn heightmap_to_image(world: &World, dir: &Path) -> Result<(), MapGenError> {
+    let heights: Vec<u16> = world.elevation.iter().map(|elevation| (elevation.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16).collect();
+    let raw: Vec<u8> = heights.iter().flat_map(|height| height.to_le_bytes()).collect();
+    let raw_file_name = dir.join("heightmap.r16");
+    std::fs::write(&raw_file_name, raw).map_err(|err| MapGenError::io(raw_file_name, err))?;
+    let file_name = dir.join("heightmap.png");
+    ImageBuffer::<Luma<u16>, Vec<u16>>::from_raw(world.width(), world.height(), heights)
+        .expect("heights has one value per pixel")
+        .save(&file_name)
+        .map_err(|err| MapGenError::Image { path: file_name, source: err })
+}
+
+pub fn continent_to_image(world: &World, dir: &Path) -> Result<(), MapGenError> {
+    let continents_image: Vec<u8> = world.continent_id.iter().flat_map(|id| match id.get() {
+        Some(id) => {
+            let color = world.continents[id as usize].color;
+            [color.0, color.1, color.2]
+        },
+        None => [0, 0, 0]
+    }).collect();
+    cells_to_image(world, &dir.join("continents.png"), continents_image)
+}
+
+pub fn province_to_image(world: &World, dir: &Path) -> Result<(), MapGenError> {
+    let buff: Vec<u8> = world.province_id.iter().flat_map(|id| match id.get() {
+        Some(id) => {
+            let province_color = world.provinces[id as usize].color;
+            [province_color.0, province_color.1, province_color.2]
+        },
+        None => [0, 0, 0]
+    }).collect();
+    cells_to_image(world, &dir.join("provinces.png"), buff)
+}
+
+pub fn resource_to_image(world: &World, resource: &str, dir: &Path) -> Result<(), MapGenError> {
+    let mut buff = Vec::with_capacity(world.area() * 3);
+    for id in world.province_id.iter() {
+        match id.get() {
+            Some(id) => {
+                let province = &world.provinces[id as usize];
+                let Some(value) = province.resources.get(resource) else {
+                    return Err(MapGenError::MissingResource { province_id: id, resource: resource.to_string() });
+                };
+                buff.extend([0, (*value).clamp(0, 255) as u8, 0]);
+            },
+            None => {
+                buff.extend([0, 0, 0]);
+            }
+        }
+    }
+    cells_to_image(world, &dir.join(format!("{resource}.png")), buff)
+}
+
+// Keeps the effective settings next to the maps, so any run can be repeated
+pub fn save_settings(settings: &Settings, file_name: &Path) -> Result<(), MapGenError> {
+    std::fs::write(file_name, settings.to_string()).map_err(|err| MapGenError::io(file_name, err))
+}
+
+// Circumradius of a hex cell in the images, in image pixels
+pub const HEX_RADIUS: f64 = 4.0;
+
+// Writes one color per cell, row by row. Square cells become one image pixel each,
+// hexes are drawn HEX_RADIUS pixels big
+pub fn cells_to_image(world: &World, result_file_name: &Path, colors: Vec<u8>) -> Result<(), MapGenError> {
+    match world.grid {
+        Grid::Square => buffer_to_image(result_file_name, colors, world.size),
+        Grid::Hex => {
+            let (buff, size) = hex_cells_to_buffer(world, &colors);
+            buffer_to_image(result_file_name, buff, size)
+        }
+    }
+}
+
+// Center of the cell in the images
+pub fn cell_center(world: &World, position: Position) -> (f64, f64) {
+    let (x, y) = (position.x as f64, position.y as f64);
+    match world.grid {
+        Grid::Square => (x + 0.5, y + 0.5),
+        Grid::Hex => (
+            3f64.sqrt() * HEX_RADIUS * (x + 0.5 + world.grid.row_shift(position.y)),
+            HEX_RADIUS * (1.5 * y + 1.0)
+        )
+    }
+}
+
+// Every image pixel takes the color of the hex it falls into, found by rounding its axial coordinates
+fn hex_cells_to_buffer(world: &World, colors: &[u8]) -> (Vec<u8>, (u32, u32)) {
+    let (columns, rows) = (world.width() as usize, world.height() as usize);
+    let width = (3f64.sqrt() * HEX_RADIUS * (columns as f64 + 0.5)).ceil() as u32;
+    let height = (HEX_RADIUS * (1.5 * rows as f64 + 0.5)).ceil() as u32;
+    let mut buff = Vec::with_capacity(width as usize * height as usize * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let x = x as f64 + 0.5 - 3f64.sqrt() / 2.0 * HEX_RADIUS;
+            let y = y as f64 + 0.5 - HEX_RADIUS;
+            let q = (3f64.sqrt() / 3.0 * x - y / 3.0) / HEX_RADIUS;
+            let r = 2.0 / 3.0 * y / HEX_RADIUS;
+            let (q, r) = round_axial(q, r);
+            let (column, row) = (q + (r - (r & 1)) / 2, r);
+            if row < 0 || column < 0 || row >= rows as i64 || column >= columns as i64 {
+                buff.extend([0, 0, 0]);
+                continue;
+            }
+            let start = (row as usize * columns + column as usize) * 3;
+            buff.extend_from_slice(&colors[start..start + 3]);
+        }
+    }
+    (buff, (width, height))
+}
+
+fn round_axial(q: f64, r: f64) -> (i64, i64) {
+    let s = -q - r;
+    let (mut rounded_q, mut rounded_r, rounded_s) = (q.round(), r.round(), s.round());
+    let (q_diff, r_diff, s_diff) = ((rounded_q - q).abs(), (rounded_r - r).abs(), (rounded_s - s).abs());
+    if q_diff > r_diff && q_diff > s_diff {
+        rounded_q = -rounded_r - rounded_s;
+    } else if r_diff > s_diff {
+        rounded_r = -rounded_q - rounded_s;
+    }
+    (rounded_q as i64, rounded_r as i64)
+}
+
+// One line per cell: its x and y, axial q and r, and the cell center in the images
+pub fn coordinates_to_file(world: &World, dir: &Path) -> Result<(), MapGenError> {
+    let file_name = dir.join("coordinates.csv");
+    let mut lines = String::from("x,y,q,r,image_x,image_y\n");
+    for position in world.positions() {
+        let (q, r) = match world.grid {
+            Grid::Square => (position.x as i64, position.y as i64),
+            Grid::Hex => world.axial(position)
+        };
+        let (image_x, image_y) = cell_center(world, position);
+        let _ = writeln!(lines, "{},{},{q},{r},{image_x:.2},{image_y:.2}", position.x, position.y);
+    }
+    std::fs::write(&file_name, lines).map_err(|err| MapGenError::io(file_name, err))
+}
+
+pub fn adjacency_to_files(world: &World, dir: &Path) -> Result<(), MapGenError> {
+    let csv_file_name = dir.join("adjacency.csv");
+    std::fs::write(&csv_file_name, world.adjacency.to_csv()).map_err(|err| MapGenError::io(csv_file_name, err))?;
+    let json_file_name = dir.join("adjacency.json");
+    std::fs::write(&json_file_name, world.adjacency.to_json()).map_err(|err| MapGenError::io(json_file_name, err))
+}
+
+pub fn metrics_to_file(world: &World, dir: &Path) -> Result<(), MapGenError> {
+    let file_name = dir.join("metrics.csv");
+    std::fs::write(&file_name, metrics_to_csv(world)).map_err(|err| MapGenError::io(file_name, err))
+}
+
+pub fn buffer_to_image(result_file_name: &Path, buff: Vec<u8>, size: (u32, u32)) -> Result<(), MapGenError> {
+    image::save_buffer(
+        result_file_name,
+        &buff,
+        size.0,
+        size.1,
+        image::ColorType::Rgb8)
+        .map_err(|err| MapGenError::Image { path: result_file_name.to_path_buf(), source: err })
+}
+
+pub fn generate_colors() -> Vec<(u8, u8, u8)> {
+    let mut colors: Vec<(u8, u8, u8)> = Vec::new();
+    for i in 0..50 {
+        for j in 0..50 {
+            for t in 0..50 {
+                colors.push((30 + i * 4, 30 + j * 4, 30 + t * 4))
+            }
+        }
+    }
+    colors
+}
+
+// Color of a province from its id, so colors never run out. Land provinces get shades from the range
+// of the continent colors, water provinces blue ones. Stepping by a prime walks every shade before one comes back,
+// and provinces with neighboring ids get far apart colors
+pub fn province_color(id: u32, is_land: bool) -> (u8, u8, u8) {
+    const STEP: u64 = 1_000_003;
+    // Lowest value and count of values of every channel
+    let channels: [(u8, u64); 3] = if is_land { [(30, 200), (30, 200), (30, 200)] } else { [(10, 75), (60, 100), (160, 80)] };
+    let shades = channels.iter().map(|(_, count)| count).product::<u64>();
+    let mut index = id as u64 * STEP % shades;
+    let mut color = [0u8; 3];
+    for (value, (lowest, count)) in color.iter_mut().zip(channels).rev() {
+        *value = lowest + (index % count) as u8;
+        index /= count;
+    }
+    (color[0], color[1], color[2])
+}
+
+pub mod necessary {
+    use std::path::Path;
+    use std::fs::create_dir;
+
+    use crate::{MapGenError, Settings};
+
+    pub const SETTINGS_FILE: &str = "gamedata/settings.txt";
+
+    // By default - create the gamedata dir with the settings file,
+    // returns what had to be created so the caller can log it
+    // TODO: Make in settings some options for this
+    pub fn check_necessary_files() -> Result<Vec<String>, MapGenError> {
+        let mut created = Vec::new();
+        check_dir("gamedata", &mut created)?;
+        if !Path::new(SETTINGS_FILE).exists() {
+            std::fs::write(SETTINGS_FILE, Settings::default().to_string()).map_err(|err| MapGenError::io(SETTINGS_FILE, err))?;
+            created.push(SETTINGS_FILE.to_string());
+        }
+        Ok(created)
+    }
+
+    fn check_dir(dir_name: &str, created: &mut Vec<String>) -> Result<(), MapGenError> {
+        if !Path::new(dir_name).exists() {
+            create_dir(dir_name).map_err(|err| MapGenError::io(dir_name, err))?;
+            created.push(dir_name.to_string());
+        }
+        Ok(())
+    }
+}
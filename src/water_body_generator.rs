@@ -0,0 +1,100 @@
+use crate::world::{Layer, MaybeId, Position};
+use crate::{Settings, World};
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaterBodyKind {
+    Ocean,
+    Sea,
+    Lake
+}
+
+impl WaterBodyKind {
+    pub const ALL: [WaterBodyKind; 3] = [WaterBodyKind::Ocean, WaterBodyKind::Sea, WaterBodyKind::Lake];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WaterBodyKind::Ocean => "ocean",
+            WaterBodyKind::Sea => "sea",
+            WaterBodyKind::Lake => "lake"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        WaterBodyKind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    pub fn to_color(&self) -> (u8, u8, u8) {
+        match self {
+            WaterBodyKind::Ocean => (20, 50, 140),
+            WaterBodyKind::Sea => (60, 120, 210),
+            WaterBodyKind::Lake => (120, 200, 240)
+        }
+    }
+}
+
+// Connected water, like a continent is connected land
+#[derive(Clone)]
+pub struct WaterBody {
+    pub id: u32,
+    pub members: Vec<Position>,
+    pub area: u32,
+    pub kind: WaterBodyKind,
+    // Surrounded by land, without touching the edge of the map or going all the way around a wrapping map
+    pub is_enclosed: bool
+}
+
+// Open water at least `ocean_min_share` of the map is an ocean, smaller open water is a sea.
+// Enclosed water up to `lake_max_share` of the map is a lake, bigger enclosed water is an inland sea
+pub fn generate_water_bodies(world: &mut World, settings: &Settings) -> Vec<WaterBody> {
+    let map_area = world.area() as f64;
+    let mut water_bodies: Vec<WaterBody> = Vec::new();
+    let mut checked = Layer::new(world.size, false);
+    // Copy of the map every pixel was reached in, counted in seam crossings east and south.
+    // Water reaching one of its pixels again from another copy goes around the map, so it's open
+    let mut copies: Layer<(i16, i16)> = Layer::new(world.size, (0, 0));
+    for start in world.positions() {
+        if checked[start] || world.terrain[start].is_land() {
+            continue;
+        }
+        let id = water_bodies.len() as u32;
+        let mut members = Vec::new();
+        let mut is_enclosed = true;
+        let mut unchecked = vec![start];
+        checked[start] = true;
+        while let Some(position) = unchecked.pop() {
+            world.water_body_id[position] = MaybeId::some(id);
+            members.push(position);
+            if world.is_edge(position) {
+                is_enclosed = false;
+            }
+            let copy = copies[position];
+            for (dx, dy) in world.neighbor_offsets(position) {
+                let Some(neighbor) = world.step(position, *dx, *dy) else {
+                    continue;
+                };
+                if world.terrain[neighbor].is_land() {
+                    continue;
+                }
+                let crossed_x = (position.x as i64 + *dx as i64 - neighbor.x as i64).signum() as i16;
+                let crossed_y = (position.y as i64 + *dy as i64 - neighbor.y as i64).signum() as i16;
+                let neighbor_copy = (copy.0.wrapping_add(crossed_x), copy.1.wrapping_add(crossed_y));
+                if !checked[neighbor] {
+                    checked[neighbor] = true;
+                    copies[neighbor] = neighbor_copy;
+                    unchecked.push(neighbor);
+                } else if copies[neighbor] != neighbor_copy {
+                    is_enclosed = false;
+                }
+            }
+        }
+        let share = members.len() as f64 / map_area;
+        let kind = match is_enclosed {
+            true if share <= settings.lake_max_share => WaterBodyKind::Lake,
+            false if share >= settings.ocean_min_share => WaterBodyKind::Ocean,
+            _ => WaterBodyKind::Sea
+        };
+        water_bodies.push(WaterBody { id, area: members.len() as u32, members, kind, is_enclosed });
+    }
+    water_bodies
+}
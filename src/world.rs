@@ -1,105 +1,441 @@
-use crate::{continent_generator::Continent, province_generator::Province};
-
-pub struct World {
-    pub seed: u32,
-    pub size: (u32, u32),
-    pub pixels: Vec<Vec<WorldPixel>>,
-    pub continents: Vec<Continent>,
-    pub provinces: Vec<Province>,
-}
-
-impl World {
-    pub fn get_pixel(&self, i: usize, j: usize) -> &WorldPixel {
-        &self.pixels[i][j]
-    }
-
-    pub fn get_pixels_around(&self, i: usize, j: usize) -> Vec<&WorldPixel> {
-        let mut pixels: Vec<&WorldPixel> = Vec::new();
-        // Up
-        if i != 0 {
-            pixels.push(self.get_pixel(i - 1, j))
-        }
-        // Down
-        if i != self.size.0 as usize - 1 {
-            pixels.push(self.get_pixel(i + 1, j))
-        }
-        // Left
-        if j != 0 {
-            pixels.push(self.get_pixel(i, j - 1))
-        }
-        // Right
-        if j != self.size.1 as usize - 1 {
-            pixels.push(self.get_pixel(i, j + 1))
-        }
-        return pixels
-    }
-
-    pub fn get_mut_pixel(&mut self, i: usize, j: usize) -> &mut WorldPixel {
-        &mut self.pixels[i][j]
-    }
-}
-
-pub struct WorldPixel {
-    pub position: (u32, u32),
-    pub terrain: Terrain,
-    pub province_id: Option<u32>,
-    pub continent_id: Option<u32>
-}
-
-#[derive(Clone)]
-pub enum Terrain {
-    Plains,
-    Mountains,
-    Hills,
-    Water
-}
-
-impl Terrain {
-    pub fn to_color(&self) -> Vec<u8>{
-        match self {
-            Terrain::Plains => {return vec![6, 169, 0]},
-            Terrain::Mountains => {return vec![40, 10, 0]},
-            Terrain::Hills => {return vec![73, 24, 0]},
-            Terrain::Water => {return vec![97, 151, 248]}
-        }
-    }
-
-    pub fn is_land(&self) -> bool {
-        match *self {
-            Terrain::Water => {return false}
-            _ => {return true}
-        }
-    }
-}
-
-impl PartialEq for Terrain {
-    fn eq(&self, other: &Self) -> bool {
-        match *self {
-            Self::Hills => {
-                match *other {
-                    Terrain::Hills => {return true},
-                    _ => {}
-                }
-            }
-            Terrain::Plains => {
-                match *other {
-                    Terrain::Plains => {return true},
-                    _ => {}
-                }
-            },
-            Terrain::Mountains => {
-                match *other {
-                    Terrain::Mountains => {return true},
-                    _ => {}
-                }
-            },
-            Terrain::Water => {
-                match *other {
-                    Terrain::Water => {return true},
-                    _ => {}
-                }
-            }
-        }
-        return false
-    }
-}
\ No newline at end of file
+use std::ops::{Deref, Index, IndexMut};
+
+use crate::{adjacency::AdjacencyGraph, continent_generator::Continent, province_generator::Province, water_body_generator::WaterBody};
+
+// Every per-pixel value lives in its own flat layer, row by row
+#[derive(Clone)]
+pub struct World {
+    pub seed: u32,
+    // Width and height
+    pub size: (u32, u32),
+    pub topology: Topology,
+    pub connectivity: Connectivity,
+    pub grid: Grid,
+    pub terrain: Layer<Terrain>,
+    // Noise value scaled to 0..1 between the lowest and the highest point of the map
+    pub elevation: Layer<f32>,
+    // 0 is the coldest and 1 the hottest, from latitude, height and noise
+    pub temperature: Layer<f32>,
+    // 0 is the driest and 1 the wettest
+    pub moisture: Layer<f32>,
+    // Terrain with the climate applied, water and relief stay, plains turn into the fitting biome
+    pub biome: Layer<Terrain>,
+    // Rain gathered from this pixel and everything upstream of it
+    pub discharge: Layer<f32>,
+    // 0 without a river
+    pub river_width: Layer<u8>,
+    // Only water pixels belong to a water body
+    pub water_body_id: Layer<MaybeId>,
+    // Land sharing a side with water, diagonals don't count
+    pub is_coastal: Layer<bool>,
+    pub province_id: Layer<MaybeId>,
+    pub continent_id: Layer<MaybeId>,
+    pub continents: Vec<Continent>,
+    pub provinces: Vec<Province>,
+    pub water_bodies: Vec<WaterBody>,
+    // Built once the provinces are final
+    pub adjacency: AdjacencyGraph
+}
+
+impl World {
+    // Plains everywhere, without climate, rivers, continents or provinces
+    pub fn new(seed: u32, size: (u32, u32), topology: Topology, connectivity: Connectivity, grid: Grid) -> Self {
+        World {
+            seed,
+            size,
+            topology,
+            connectivity,
+            grid,
+            terrain: Layer::new(size, Terrain::Plains),
+            elevation: Layer::new(size, 0.0),
+            temperature: Layer::new(size, 0.0),
+            moisture: Layer::new(size, 0.0),
+            biome: Layer::new(size, Terrain::Plains),
+            discharge: Layer::new(size, 0.0),
+            river_width: Layer::new(size, 0),
+            water_body_id: Layer::new(size, MaybeId::NONE),
+            is_coastal: Layer::new(size, false),
+            province_id: Layer::new(size, MaybeId::NONE),
+            continent_id: Layer::new(size, MaybeId::NONE),
+            continents: Vec::new(),
+            provinces: Vec::new(),
+            water_bodies: Vec::new(),
+            adjacency: AdjacencyGraph::default()
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.size.0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.size.1
+    }
+
+    // Number of pixels
+    pub fn area(&self) -> usize {
+        self.size.0 as usize * self.size.1 as usize
+    }
+
+    // Place of the position in every layer
+    pub fn index(&self, position: Position) -> usize {
+        position.y as usize * self.size.0 as usize + position.x as usize
+    }
+
+    pub fn position(&self, index: usize) -> Position {
+        Position::new((index % self.size.0 as usize) as u32, (index / self.size.0 as usize) as u32)
+    }
+
+    // Every position row by row, in the same order as the layers
+    pub fn positions(&self) -> impl Iterator<Item = Position> {
+        let (width, height) = self.size;
+        (0..height).flat_map(move |y| (0..width).map(move |x| Position::new(x, y)))
+    }
+
+    // Positions next to this one in the order up, down, left, right, then the diagonals for Moore connectivity,
+    // or the 6 cells around a hex. Goes across the seams when the world wraps
+    pub fn neighbors(&self, position: Position) -> Neighbors {
+        let mut neighbors = Neighbors { positions: [Position::default(); 8], len: 0 };
+        for (dx, dy) in self.neighbor_offsets(position) {
+            let Some(next) = self.step(position, *dx, *dy) else {
+                continue;
+            };
+            // On maps one or two pixels wide the seam leads back to the same pixels
+            if next != position && !neighbors.contains(&next) {
+                neighbors.positions[neighbors.len] = next;
+                neighbors.len += 1;
+            }
+        }
+        neighbors
+    }
+
+    // (x, y) steps to the neighbors of the position
+    pub fn neighbor_offsets(&self, position: Position) -> &'static [(i32, i32)] {
+        match self.grid {
+            Grid::Square => self.connectivity.offsets(),
+            Grid::Hex => Grid::hex_offsets(position.y)
+        }
+    }
+
+    // (x, y) steps to the cells across every side, 4 for pixels whatever the connectivity and 6 for hexes
+    pub fn side_offsets(&self, position: Position) -> &'static [(i32, i32)] {
+        match self.grid {
+            Grid::Square => Connectivity::VonNeumann.offsets(),
+            Grid::Hex => Grid::hex_offsets(position.y)
+        }
+    }
+
+    // The position (dx, dy) away, across the seams when the world wraps, None past an edge that doesn't
+    pub fn step(&self, position: Position, dx: i32, dy: i32) -> Option<Position> {
+        let (width, height) = (self.size.0 as i64, self.size.1 as i64);
+        let (mut x, mut y) = (position.x as i64 + dx as i64, position.y as i64 + dy as i64);
+        if !(0..height).contains(&y) {
+            if !self.topology.wraps_north_south() {
+                return None;
+            }
+            y = y.rem_euclid(height);
+        }
+        if !(0..width).contains(&x) {
+            if !self.topology.wraps_east_west() {
+                return None;
+            }
+            x = x.rem_euclid(width);
+        }
+        Some(Position::new(x as u32, y as u32))
+    }
+
+    // Axial (q, r) of a hex cell, rows stay rows and columns lean with them
+    pub fn axial(&self, position: Position) -> (i64, i64) {
+        let (x, y) = (position.x as i64, position.y as i64);
+        (x - (y - (y & 1)) / 2, y)
+    }
+
+    // Pixels on a side of the map that doesn't wrap
+    pub fn is_edge(&self, position: Position) -> bool {
+        let (width, height) = self.size;
+        (!self.topology.wraps_north_south() && (position.y == 0 || position.y == height - 1))
+            || (!self.topology.wraps_east_west() && (position.x == 0 || position.x == width - 1))
+    }
+}
+
+// The neighbors of a position, at most 8 of them, kept on the stack since they're asked for every pixel
+#[derive(Clone, Copy, Debug)]
+pub struct Neighbors {
+    positions: [Position; 8],
+    len: usize
+}
+
+impl Deref for Neighbors {
+    type Target = [Position];
+
+    fn deref(&self) -> &[Position] {
+        &self.positions[..self.len]
+    }
+}
+
+impl IntoIterator for Neighbors {
+    type Item = Position;
+    type IntoIter = std::iter::Take<std::array::IntoIter<Position, 8>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.positions.into_iter().take(self.len)
+    }
+}
+
+// x grows to the east (right in the images), y to the south (down)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub x: u32,
+    pub y: u32
+}
+
+impl Position {
+    pub fn new(x: u32, y: u32) -> Self {
+        Position { x, y }
+    }
+}
+
+// One value per pixel, stored row by row
+#[derive(Clone, Debug)]
+pub struct Layer<T> {
+    width: u32,
+    values: Vec<T>
+}
+
+impl<T: Clone> Layer<T> {
+    pub fn new(size: (u32, u32), value: T) -> Self {
+        Layer { width: size.0, values: vec![value; size.0 as usize * size.1 as usize] }
+    }
+}
+
+impl<T> Layer<T> {
+    // `values` go row by row
+    pub fn from_values(size: (u32, u32), values: Vec<T>) -> Self {
+        assert_eq!(values.len(), size.0 as usize * size.1 as usize, "a layer needs one value per pixel");
+        Layer { width: size.0, values }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.values.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.values.iter_mut()
+    }
+
+    // Row by row, the same order as `World::positions` and `World::index`
+    pub fn as_slice(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.values
+    }
+}
+
+impl<T> Index<Position> for Layer<T> {
+    type Output = T;
+
+    fn index(&self, position: Position) -> &T {
+        &self.values[position.y as usize * self.width as usize + position.x as usize]
+    }
+}
+
+impl<T> IndexMut<Position> for Layer<T> {
+    fn index_mut(&mut self, position: Position) -> &mut T {
+        &mut self.values[position.y as usize * self.width as usize + position.x as usize]
+    }
+}
+
+// Optional province, continent or water body id in 4 bytes instead of the 8 of Option<u32>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaybeId(u32);
+
+impl MaybeId {
+    pub const NONE: MaybeId = MaybeId(u32::MAX);
+
+    pub fn some(id: u32) -> Self {
+        debug_assert!(id != u32::MAX, "u32::MAX means no id");
+        MaybeId(id)
+    }
+
+    pub fn get(self) -> Option<u32> {
+        (self != MaybeId::NONE).then_some(self.0)
+    }
+
+    pub fn is_some(self) -> bool {
+        self != MaybeId::NONE
+    }
+
+    pub fn is_none(self) -> bool {
+        self == MaybeId::NONE
+    }
+}
+
+// Flat stops at every edge, a cylinder wraps east-west and a torus wraps both ways
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Topology {
+    Flat,
+    Cylinder,
+    Torus
+}
+
+impl Topology {
+    pub const ALL: [Topology; 3] = [Topology::Flat, Topology::Cylinder, Topology::Torus];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Topology::Flat => "flat",
+            Topology::Cylinder => "cylinder",
+            Topology::Torus => "torus"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Topology::ALL.into_iter().find(|topology| topology.name() == name)
+    }
+
+    pub fn wraps_east_west(&self) -> bool {
+        matches!(self, Topology::Cylinder | Topology::Torus)
+    }
+
+    pub fn wraps_north_south(&self) -> bool {
+        matches!(self, Topology::Torus)
+    }
+}
+
+// Von Neumann counts the 4 pixels sharing a side as neighbors, Moore the 8 sharing a side or a corner
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connectivity {
+    VonNeumann,
+    Moore
+}
+
+impl Connectivity {
+    pub const ALL: [Connectivity; 2] = [Connectivity::VonNeumann, Connectivity::Moore];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Connectivity::VonNeumann => "von_neumann",
+            Connectivity::Moore => "moore"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Connectivity::ALL.into_iter().find(|connectivity| connectivity.name() == name)
+    }
+
+    // (x, y) steps to the neighbors
+    pub fn offsets(&self) -> &'static [(i32, i32)] {
+        match self {
+            Connectivity::VonNeumann => &[(0, -1), (0, 1), (-1, 0), (1, 0)],
+            Connectivity::Moore => &[(0, -1), (0, 1), (-1, 0), (1, 0), (-1, -1), (1, -1), (-1, 1), (1, 1)]
+        }
+    }
+}
+
+// Square pixels, or pointy-top hexes stored in offset rows where every odd row is shifted half a cell right.
+// Hexes always have 6 neighbors, connectivity only applies to squares
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grid {
+    Square,
+    Hex
+}
+
+impl Grid {
+    pub const ALL: [Grid; 2] = [Grid::Square, Grid::Hex];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Grid::Square => "square",
+            Grid::Hex => "hex"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Grid::ALL.into_iter().find(|grid| grid.name() == name)
+    }
+
+    // (x, y) steps to the hexes around a cell of row `y`: up, down, left, right, then the other two
+    pub fn hex_offsets(y: u32) -> &'static [(i32, i32)] {
+        if y % 2 == 1 {
+            &[(0, -1), (0, 1), (-1, 0), (1, 0), (1, -1), (1, 1)]
+        } else {
+            &[(0, -1), (0, 1), (-1, 0), (1, 0), (-1, -1), (-1, 1)]
+        }
+    }
+
+    // How far row `y` is shifted right, in cells
+    pub fn row_shift(&self, y: u32) -> f64 {
+        match self {
+            Grid::Hex if y % 2 == 1 => 0.5,
+            _ => 0.0
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Terrain {
+    Plains,
+    Mountains,
+    Hills,
+    Water,
+    Desert,
+    Steppe,
+    Forest,
+    Jungle,
+    Taiga,
+    Tundra,
+    Swamp,
+    Glacier
+}
+
+impl Terrain {
+    pub const ALL: [Terrain; 12] = [
+        Terrain::Plains, Terrain::Mountains, Terrain::Hills, Terrain::Water,
+        Terrain::Desert, Terrain::Steppe, Terrain::Forest, Terrain::Jungle,
+        Terrain::Taiga, Terrain::Tundra, Terrain::Swamp, Terrain::Glacier
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Terrain::Plains => "plains",
+            Terrain::Mountains => "mountains",
+            Terrain::Hills => "hills",
+            Terrain::Water => "water",
+            Terrain::Desert => "desert",
+            Terrain::Steppe => "steppe",
+            Terrain::Forest => "forest",
+            Terrain::Jungle => "jungle",
+            Terrain::Taiga => "taiga",
+            Terrain::Tundra => "tundra",
+            Terrain::Swamp => "swamp",
+            Terrain::Glacier => "glacier"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Terrain::ALL.into_iter().find(|terrain| terrain.name() == name)
+    }
+
+    pub fn to_color(&self) -> Vec<u8>{
+        match self {
+            Terrain::Plains => vec![6, 169, 0],
+            Terrain::Mountains => vec![40, 10, 0],
+            Terrain::Hills => vec![73, 24, 0],
+            Terrain::Water => vec![97, 151, 248],
+            Terrain::Desert => vec![237, 201, 124],
+            Terrain::Steppe => vec![181, 178, 88],
+            Terrain::Forest => vec![20, 110, 30],
+            Terrain::Jungle => vec![0, 72, 28],
+            Terrain::Taiga => vec![56, 102, 86],
+            Terrain::Tundra => vec![150, 160, 138],
+            Terrain::Swamp => vec![78, 96, 52],
+            Terrain::Glacier => vec![236, 244, 252]
+        }
+    }
+
+    pub fn is_land(&self) -> bool {
+        !matches!(*self, Terrain::Water)
+    }
+}
@@ -0,0 +1,93 @@
+use map_generator::context::GenerationContext;
+use map_generator::generate_world_in;
+use map_generator::province_generator::ProvinceShape;
+use map_generator::settings::Settings;
+use map_generator::world::{Connectivity, Grid, Layer, Topology, World};
+
+
+fn generate(settings: &Settings, threads: usize) -> World {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    pool.install(|| generate_world_in(settings, &mut GenerationContext::new()).unwrap())
+}
+
+fn assert_same_layer<T: PartialEq + std::fmt::Debug>(name: &str, first: &Layer<T>, second: &Layer<T>) {
+    let mismatch = first.as_slice().iter().zip(second.as_slice()).position(|(first, second)| first != second);
+    assert_eq!(mismatch, None, "layer {name} differs at pixel {mismatch:?}");
+}
+
+// f32 by bits, so even a different NaN counts
+fn assert_same_float_layer(name: &str, first: &Layer<f32>, second: &Layer<f32>) {
+    let mismatch = first.as_slice().iter().zip(second.as_slice()).position(|(first, second)| first.to_bits() != second.to_bits());
+    assert_eq!(mismatch, None, "layer {name} differs at pixel {mismatch:?}");
+}
+
+fn assert_same_world(first: &World, second: &World) {
+    assert_eq!(first.size, second.size);
+    assert_same_layer("terrain", &first.terrain, &second.terrain);
+    assert_same_float_layer("elevation", &first.elevation, &second.elevation);
+    assert_same_float_layer("temperature", &first.temperature, &second.temperature);
+    assert_same_float_layer("moisture", &first.moisture, &second.moisture);
+    assert_same_layer("biome", &first.biome, &second.biome);
+    assert_same_float_layer("discharge", &first.discharge, &second.discharge);
+    assert_same_layer("river_width", &first.river_width, &second.river_width);
+    assert_same_layer("water_body_id", &first.water_body_id, &second.water_body_id);
+    assert_same_layer("is_coastal", &first.is_coastal, &second.is_coastal);
+    assert_same_layer("continent_id", &first.continent_id, &second.continent_id);
+    assert_same_layer("province_id", &first.province_id, &second.province_id);
+
+    assert_eq!(first.water_bodies.len(), second.water_bodies.len());
+    for (first, second) in first.water_bodies.iter().zip(second.water_bodies.iter()) {
+        assert_eq!((first.id, &first.members, first.kind, first.is_enclosed), (second.id, &second.members, second.kind, second.is_enclosed));
+    }
+    assert_eq!(first.continents.len(), second.continents.len());
+    for (first, second) in first.continents.iter().zip(second.continents.iter()) {
+        assert_eq!((first.id, &first.members, first.color, first.metrics), (second.id, &second.members, second.color, second.metrics), "continent {}", first.id);
+    }
+    assert_eq!(first.provinces.len(), second.provinces.len());
+    for (first, second) in first.provinces.iter().zip(second.provinces.iter()) {
+        let id = first.id;
+        assert_eq!((first.id, first.continent_id, first.is_land, first.color), (second.id, second.continent_id, second.is_land, second.color), "province {id}");
+        assert_eq!(first.elements, second.elements, "elements of province {id}");
+        assert_eq!(first.neighbors, second.neighbors, "neighbors of province {id}");
+        assert_eq!(first.resources, second.resources, "resources of province {id}");
+        assert_eq!(
+            (first.is_coastal, first.coastline_length, &first.water_body_ids, &first.sea_province_ids),
+            (second.is_coastal, second.coastline_length, &second.water_body_ids, &second.sea_province_ids),
+            "coast of province {id}"
+        );
+        assert_eq!(first.metrics, second.metrics, "metrics of province {id}");
+    }
+    assert_eq!(first.adjacency.edges(), second.adjacency.edges());
+}
+
+fn small_settings() -> Settings {
+    Settings { world_size: (192, 128), seed: 31337, min_province_size: 10, max_province_size: 40, ..Settings::default() }
+}
+
+#[test]
+fn same_seed_gives_the_same_world() {
+    let settings = small_settings();
+    let world = generate(&settings, 2);
+    assert!(!world.provinces.is_empty());
+    assert_same_world(&world, &generate(&settings, 2));
+}
+
+#[test]
+fn thread_count_does_not_change_the_world() {
+    let settings = small_settings();
+    assert_same_world(&generate(&settings, 1), &generate(&settings, 4));
+}
+
+#[test]
+fn thread_count_does_not_change_other_shapes_and_topologies() {
+    for province_shape in [ProvinceShape::Voronoi, ProvinceShape::Flood] {
+        let settings = Settings {
+            topology: Topology::Torus,
+            connectivity: Connectivity::Moore,
+            grid: Grid::Hex,
+            province_shape,
+            ..small_settings()
+        };
+        assert_same_world(&generate(&settings, 1), &generate(&settings, 4));
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+
+use map_generator::context::GenerationContext;
+use map_generator::generate_world_in;
+use map_generator::province_generator::{Province, ProvinceShape};
+use map_generator::settings::Settings;
+use map_generator::world::{Position, World};
+
+
+fn small_settings() -> Settings {
+    Settings { world_size: (192, 128), seed: 31337, min_province_size: 10, max_province_size: 40, ..Settings::default() }
+}
+
+// Pixels reachable from the first element without leaving the province
+fn reachable(world: &World, province: &Province) -> usize {
+    let Some(&start) = province.elements.first() else {
+        return 0;
+    };
+    let mut visited: HashSet<Position> = HashSet::from([start]);
+    let mut queue = vec![start];
+    while let Some(position) = queue.pop() {
+        for neighbor in world.neighbors(position) {
+            if world.province_id[neighbor].get() == Some(province.id) && visited.insert(neighbor) {
+                queue.push(neighbor);
+            }
+        }
+    }
+    visited.len()
+}
+
+fn assert_connected(world: &World, province: &Province, shape: ProvinceShape) {
+    assert_eq!(reachable(world, province), province.elements.len(), "province {} of {} is in several pieces", province.id, shape.name());
+}
+
+#[test]
+fn every_land_province_is_one_piece() {
+    for province_shape in ProvinceShape::ALL {
+        let world = generate_world_in(&Settings { province_shape, ..small_settings() }, &mut GenerationContext::new()).unwrap();
+        assert!(world.provinces.iter().any(|province| province.is_land));
+        for province in world.provinces.iter().filter(|province| province.is_land) {
+            assert_connected(&world, province, province_shape);
+        }
+    }
+}
+
+// Every water province keeps to the bounds, except a lake with no other water province to join
+#[test]
+fn water_provinces_keep_to_the_bounds_in_one_piece() {
+    let settings = small_settings();
+    let world = generate_world_in(&settings, &mut GenerationContext::new()).unwrap();
+    assert!(world.provinces.iter().any(|province| !province.is_land));
+    for province in world.provinces.iter().filter(|province| !province.is_land) {
+        let size = province.elements.len() as u32;
+        assert!(size <= settings.max_water_province_size, "water province {} has {size} pixels", province.id);
+        let isolated = province.neighbors.iter().all(|neighbor| world.provinces[*neighbor as usize].is_land);
+        assert!(size >= settings.min_water_province_size || isolated, "water province {} has {size} pixels", province.id);
+        assert!(province.elements.iter().all(|position| !world.terrain[*position].is_land()), "water province {} has land", province.id);
+        assert_connected(&world, province, settings.province_shape);
+    }
+}
+
+#[test]
+fn land_provinces_hold_only_land_and_list_only_sea_provinces() {
+    let world = generate_world_in(&small_settings(), &mut GenerationContext::new()).unwrap();
+    for province in world.provinces.iter().filter(|province| province.is_land) {
+        assert!(province.elements.iter().all(|position| world.terrain[*position].is_land()), "land province {} has water", province.id);
+        assert!(province.sea_province_ids.iter().all(|id| !world.provinces[*id as usize].is_land), "land province {} lists a land province as sea", province.id);
+    }
+}